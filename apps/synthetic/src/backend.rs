@@ -91,6 +91,32 @@ impl Backend {
         }
     }
 
+    /// Runs `f` once `duration` has passed, without blocking the caller.
+    pub fn run_after<F>(&self, duration: Duration, f: F)
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        match *self {
+            Backend::Linux => {
+                thread::spawn(move || {
+                    thread::sleep(duration);
+                    f()
+                });
+            }
+            Backend::Runtime => {
+                shenango::timer::Timer::after(duration, f);
+            }
+        }
+    }
+
+    pub fn barrier(&self, n: usize) -> Barrier {
+        match *self {
+            Backend::Linux => Barrier::Linux(std::sync::Barrier::new(n)),
+            Backend::Runtime => Barrier::Runtime(shenango::sync::Barrier::new(n)),
+        }
+    }
+
     #[allow(unused)]
     pub fn thread_yield(&self) {
         match *self {
@@ -179,6 +205,15 @@ impl Connection {
         }
     }
 
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Connection::LinuxUdp(ref s) => s.set_read_timeout(timeout),
+            Connection::LinuxTcp(ref s) => s.set_read_timeout(timeout),
+            Connection::RuntimeUdp(ref s) => s.set_read_timeout(timeout),
+            Connection::RuntimeTcp(ref s) => s.set_read_timeout(timeout),
+        }
+    }
+
     #[allow(unused)]
     pub fn shutdown(&self) {
         match *self {
@@ -280,3 +315,16 @@ impl<T: Send + 'static> JoinHandle<T> {
         }
     }
 }
+
+pub enum Barrier {
+    Linux(std::sync::Barrier),
+    Runtime(shenango::sync::Barrier),
+}
+impl Barrier {
+    pub fn wait(&self) -> bool {
+        match *self {
+            Barrier::Linux(ref b) => b.wait().is_leader(),
+            Barrier::Runtime(ref b) => b.wait().is_leader(),
+        }
+    }
+}
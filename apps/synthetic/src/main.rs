@@ -20,9 +20,8 @@ use std::f32::INFINITY;
 use std::io;
 use std::io::{ErrorKind, Read, Write};
 use std::net::{Ipv4Addr, SocketAddrV4};
-use std::slice;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
@@ -179,9 +178,8 @@ fn socket_worker(socket: &mut Connection, worker: Arc<FakeWorker>) {
     };
     loop {
         if let Err(e) = r() as io::Result<()> {
-            match e.raw_os_error() {
-                Some(-104) | Some(104) => break,
-                _ => {}
+            if e.kind() == ErrorKind::ConnectionReset {
+                break;
             }
             if e.kind() != ErrorKind::UnexpectedEof {
                 println!("Receive thread: {}", e);
@@ -208,22 +206,13 @@ fn run_tcp_server(backend: Backend, addr: SocketAddrV4, worker: Arc<FakeWorker>)
 }
 
 fn run_spawner_server(addr: SocketAddrV4, workerspec: &str) {
-    static mut SPAWNER_WORKER: Option<FakeWorker> = None;
-    unsafe {
-        SPAWNER_WORKER = Some(FakeWorker::create(workerspec).unwrap());
-    }
-    extern "C" fn echo(d: *mut shenango::ffi::udp_spawn_data) {
-        unsafe {
-            let buf = slice::from_raw_parts((*d).buf as *mut u8, (*d).len);
-            let payload = Payload::deserialize(&mut &buf[..]).unwrap();
-            let worker = SPAWNER_WORKER.as_ref().unwrap();
-            worker.work(payload.work_iterations, payload.randomness);
-            let _ = UdpSpawner::reply(d, buf);
-            UdpSpawner::release_data(d);
-        }
-    }
-
-    let _s = unsafe { UdpSpawner::new(addr, echo).unwrap() };
+    let worker = FakeWorker::create(workerspec).unwrap();
+    let _s = UdpSpawner::new(addr, move |req| {
+        let payload = Payload::deserialize(&mut &req.payload()[..]).unwrap();
+        worker.work(payload.work_iterations, payload.randomness);
+        let _ = req.respond(req.payload());
+    })
+    .unwrap();
 
     let wg = shenango::WaitGroup::new();
     wg.add(1);
@@ -242,20 +231,15 @@ fn run_memcached_preload(
         .map(|i| {
             let proto = proto.clone();
             backend.spawn_thread(move || {
-                let sock1 = Arc::new(match tport {
+                let sock1 = match tport {
                     Transport::Tcp => backend.create_tcp_connection(None, addr).unwrap(),
                     Transport::Udp => backend
                         .create_udp_connection("0.0.0.0:0".parse().unwrap(), Some(addr))
                         .unwrap(),
-                });
-                let socket = sock1.clone();
-                backend.spawn_thread(move || {
-                    backend.sleep(Duration::from_secs(520));
-                    if Arc::strong_count(&socket) > 1 {
-                        println!("Timing out socket");
-                        socket.shutdown();
-                    }
-                });
+                };
+                sock1
+                    .set_read_timeout(Some(Duration::from_secs(10)))
+                    .unwrap();
 
                 let mut vec_s: Vec<u8> = Vec::with_capacity(4096);
                 let mut vec_r: Vec<u8> = vec![0; 4096];
@@ -263,7 +247,7 @@ fn run_memcached_preload(
                     vec_s.clear();
                     proto.set_request((i * perthread + n) as u64, 0, &mut vec_s);
 
-                    if let Err(e) = (&*sock1).write_all(&vec_s[..]) {
+                    if let Err(e) = (&sock1).write_all(&vec_s[..]) {
                         println!("Preload send ({}/{}): {}", n, perthread, e);
                         return false;
                     }
@@ -587,7 +571,8 @@ fn run_client_worker(
     addr: SocketAddrV4,
     tport: Transport,
     wg: shenango::WaitGroup,
-    wg_start: shenango::WaitGroup,
+    start_barrier: Arc<Barrier>,
+    finish_barrier: Arc<Barrier>,
     schedules: Arc<Vec<RequestSchedule>>,
     index: usize,
 ) -> Vec<Option<ScheduleResult>> {
@@ -635,8 +620,8 @@ fn run_client_worker(
             match rproto.read_response(&socket2, &mut recv_buf[..]) {
                 Ok((idx, tsc)) => receive_times[idx] = Some((Instant::now(), tsc)),
                 Err(e) => {
-                    match e.raw_os_error() {
-                        Some(-103) | Some(-104) => break,
+                    match e.kind() {
+                        ErrorKind::ConnectionAborted | ErrorKind::ConnectionReset => break,
                         _ => (),
                     }
                     if e.kind() != ErrorKind::UnexpectedEof {
@@ -649,25 +634,20 @@ fn run_client_worker(
         receive_times
     });
 
+    wg.done();
+    start_barrier.wait();
+    let start = Instant::now();
+
     // If the send or receive thread is still running 500 ms after it should have finished,
     // then stop it by triggering a shutdown on the socket.
     let last = packets[packets.len() - 1].target_start;
     let socket2 = socket.clone();
-    let wg2 = wg.clone();
-    let wg3 = wg_start.clone();
-    let timer = backend.spawn_thread(move || {
-        wg2.done();
-        wg3.wait();
-        backend.sleep(last + Duration::from_millis(500));
+    backend.run_after(last + Duration::from_millis(500), move || {
         if Arc::strong_count(&socket2) > 1 {
             socket2.shutdown();
         }
     });
 
-    wg.done();
-    wg_start.wait();
-    let start = Instant::now();
-
     for (i, packet) in packets.iter_mut().enumerate() {
         payload.clear();
         proto.gen_req(i, packet, &mut payload);
@@ -684,18 +664,18 @@ fn run_client_worker(
         packet.actual_start = Some(start.elapsed());
         if let Err(e) = (&*socket).write_all(&payload[..]) {
             packet.actual_start = None;
-            match e.raw_os_error() {
-                Some(-32) | Some(-103) | Some(-104) => {}
+            match e.kind() {
+                ErrorKind::BrokenPipe
+                | ErrorKind::ConnectionAborted
+                | ErrorKind::ConnectionReset => {}
                 _ => println!("Send thread ({}/{}): {}", i, packets.len(), e),
             }
             break;
         }
     }
 
-    wg.done();
-    wg_start.wait();
+    finish_barrier.wait();
 
-    timer.join().unwrap();
     receive_thread
         .join()
         .unwrap()
@@ -732,9 +712,9 @@ fn run_client(
 ) -> bool {
     let schedules = Arc::new(schedules);
     let wg = shenango::WaitGroup::new();
-    wg.add(3 * nthreads as i32);
-    let wg_start = shenango::WaitGroup::new();
-    wg_start.add(1 as i32);
+    wg.add(2 * nthreads as i32);
+    let start_barrier = Arc::new(backend.barrier(nthreads + 1));
+    let finish_barrier = Arc::new(backend.barrier(nthreads + 1));
 
     let conn_threads: Vec<_> = (0..nthreads)
         .into_iter()
@@ -742,12 +722,21 @@ fn run_client(
             let client_idx = 100 + (index * nthreads) + i;
             let proto = proto.clone();
             let wg = wg.clone();
-            let wg_start = wg_start.clone();
+            let start_barrier = start_barrier.clone();
+            let finish_barrier = finish_barrier.clone();
             let schedules = schedules.clone();
 
             backend.spawn_thread(move || {
                 run_client_worker(
-                    proto, backend, addr, tport, wg, wg_start, schedules, client_idx,
+                    proto,
+                    backend,
+                    addr,
+                    tport,
+                    wg,
+                    start_barrier,
+                    finish_barrier,
+                    schedules,
+                    client_idx,
                 )
             })
         })
@@ -760,14 +749,10 @@ fn run_client(
         g.barrier();
     }
 
-    wg_start.done();
+    start_barrier.wait();
     let start_unix = SystemTime::now();
 
-    wg.add(nthreads as i32);
-    wg_start.add(1 as i32);
-
-    wg.wait();
-    wg_start.done();
+    finish_barrier.wait();
 
     let mut packets: Vec<Vec<Option<ScheduleResult>>> = conn_threads
         .into_iter()
@@ -829,7 +814,7 @@ fn run_local(
         let worker = worker.clone();
         let schedules = schedules.clone();
         send_threads.push(backend.spawn_thread(move || {
-            let remaining = Arc::new(AtomicUsize::new(packets.len()));
+            let mut work_threads = Vec::with_capacity(packets.len());
             for i in 0..packets.len() {
                 let (work_iterations, completion_time_ns, rnd) = {
                     let packet = &mut packets[i];
@@ -847,20 +832,18 @@ fn run_local(
                     )
                 };
 
-                let remaining = remaining.clone();
                 let worker = worker.clone();
-                backend.spawn_thread(move || {
+                work_threads.push(backend.spawn_thread(move || {
                     worker.work(work_iterations, rnd);
                     unsafe {
                         (*completion_time_ns.0)
                             .store(start.elapsed().as_nanos() as u64, Ordering::SeqCst);
                     }
-                    remaining.fetch_sub(1, Ordering::SeqCst);
-                });
+                }));
             }
 
-            while remaining.load(Ordering::SeqCst) > 0 {
-                // do nothing
+            for t in work_threads {
+                t.join().unwrap();
             }
 
             for p in packets.iter_mut() {
@@ -1132,14 +1115,13 @@ fn main() {
     if matches.is_present("calibrate") {
         let us = value_t_or_exit!(matches, "calibrate", u64);
         backend.init_and_run(config, move || {
-            let barrier = Arc::new(AtomicUsize::new(nthreads));
+            let barrier = Arc::new(backend.barrier(nthreads));
             let join_handles: Vec<_> = (0..nthreads)
                 .map(|_| {
                     let fakeworker = fakeworker.clone();
                     let barrier = barrier.clone();
                     backend.spawn_thread(move || {
-                        barrier.fetch_sub(1, Ordering::SeqCst);
-                        while barrier.load(Ordering::SeqCst) > 0 {}
+                        barrier.wait();
                         fakeworker.calibrate(us);
                     })
                 })
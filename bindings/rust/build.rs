@@ -10,6 +10,16 @@ fn main() {
     build_deps::rerun_if_changed_paths("../../inc/**").unwrap();
     build_deps::rerun_if_changed_paths("../../*.a").unwrap();
 
+    let breakwater = env::var("CARGO_FEATURE_BREAKWATER").is_ok();
+    if breakwater {
+        build_deps::rerun_if_changed_paths("../../breakwater/inc/**").unwrap();
+        build_deps::rerun_if_changed_paths("../../breakwater/*.a").unwrap();
+
+        // libbw depends on the runtime, so it must come first.
+        println!("cargo:rustc-link-lib=static=bw");
+        println!("cargo:rustc-flags=-L ../../breakwater");
+    }
+
     // Tell cargo to tell rustc to link the library.
     println!("cargo:rustc-link-lib=static=base");
     println!("cargo:rustc-link-lib=static=net");
@@ -33,8 +43,13 @@ fn main() {
     // The bindgen::Builder is the main entry point
     // to bindgen, and lets you build up options for
     // the resulting bindings.
-    let bindings = bindgen::Builder::default()
-        .clang_arg("-I../../inc/")
+    let mut builder = bindgen::Builder::default().clang_arg("-I../../inc/");
+    if breakwater {
+        builder = builder
+            .clang_arg("-I../../breakwater/inc/")
+            .clang_arg("-DSHENANGO_BREAKWATER");
+    }
+    let bindings = builder
         // The input header we would like to generate
         // bindings for.
         .header("shenango.h")
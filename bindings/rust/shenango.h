@@ -8,6 +8,7 @@
 #include <base/slab.h>
 #include <base/tcache.h>
 
+#include <runtime/poll.h>
 #include <runtime/preempt.h>
 #include <runtime/runtime.h>
 #include <runtime/smalloc.h>
@@ -17,3 +18,7 @@
 #include <runtime/thread.h>
 #include <runtime/timer.h>
 #include <runtime/udp.h>
+
+#ifdef SHENANGO_BREAKWATER
+#include <breakwater/rpc.h>
+#endif
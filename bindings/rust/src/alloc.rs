@@ -0,0 +1,124 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cmp;
+use std::os::raw::c_void;
+use std::ptr;
+
+use super::*;
+
+extern "C" {
+    #[link_name = "smalloc_thread_ready"]
+    #[thread_local]
+    static smalloc_thread_ready: bool;
+}
+
+/// Every smalloc item is at least this aligned.
+const SMALLOC_ALIGN: usize = 16;
+/// The largest item smalloc can hand out (SMALLOC_MAX_SIZE in smalloc.c).
+const SMALLOC_MAX_SIZE: usize = SMALLOC_ALIGN << 14;
+
+#[inline]
+fn size_class(size: usize) -> usize {
+    cmp::max(size, SMALLOC_ALIGN).next_power_of_two()
+}
+
+/// A global allocator that serves requests from the runtime's per-kthread
+/// smalloc caches, e.g.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: shenango::alloc::SmallocAllocator = shenango::alloc::SmallocAllocator;
+/// ```
+///
+/// Threads that are not runtime kthreads (including the main thread before
+/// `runtime_init`), and requests larger than smalloc's biggest size class, use
+/// the system allocator instead. smalloc memory freed from such a thread is
+/// leaked rather than returned to a cache the thread does not own.
+pub struct SmallocAllocator;
+
+impl SmallocAllocator {
+    unsafe fn smalloc(&self, layout: Layout, zeroed: bool) -> *mut u8 {
+        if !smalloc_thread_ready {
+            return ptr::null_mut();
+        }
+
+        // Over-allocate to meet larger alignments, and stash the original
+        // pointer just below the one we return.
+        let extra = if layout.align() > SMALLOC_ALIGN {
+            layout.align()
+        } else {
+            0
+        };
+        let size = layout.size() + extra;
+        if size > SMALLOC_MAX_SIZE {
+            return ptr::null_mut();
+        }
+
+        let raw = if zeroed {
+            ffi::__szalloc(size) as *mut u8
+        } else {
+            ffi::smalloc(size) as *mut u8
+        };
+        if raw.is_null() || extra == 0 {
+            return raw;
+        }
+
+        let aligned = ((raw as usize + 1 + layout.align() - 1) & !(layout.align() - 1)) as *mut u8;
+        *(aligned as *mut *mut u8).offset(-1) = raw;
+        aligned
+    }
+}
+
+unsafe impl GlobalAlloc for SmallocAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let p = self.smalloc(layout, false);
+        if p.is_null() {
+            System.alloc(layout)
+        } else {
+            p
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let p = self.smalloc(layout, true);
+        if p.is_null() {
+            System.alloc_zeroed(layout)
+        } else {
+            p
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        if !ffi::smalloc_owns(ptr as *mut c_void) {
+            System.dealloc(ptr, layout);
+            return;
+        }
+
+        let raw = if layout.align() > SMALLOC_ALIGN {
+            *(ptr as *mut *mut u8).offset(-1)
+        } else {
+            ptr
+        };
+        if smalloc_thread_ready {
+            ffi::sfree(raw as *mut c_void);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // smalloc rounds up to power-of-two size classes, so there is often
+        // room to grow or shrink in place.
+        if layout.align() <= SMALLOC_ALIGN
+            && size_class(new_size) == size_class(layout.size())
+            && ffi::smalloc_owns(ptr as *mut c_void)
+        {
+            return ptr;
+        }
+
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let new = self.alloc(new_layout);
+        if !new.is_null() {
+            ptr::copy_nonoverlapping(ptr, new, cmp::min(layout.size(), new_size));
+            self.dealloc(ptr, layout);
+        }
+        new
+    }
+}
@@ -0,0 +1,194 @@
+use std::cmp;
+use std::fmt;
+use std::io;
+use std::slice;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::{panic, ptr};
+
+use super::*;
+
+/// The largest request or response payload, in bytes.
+pub const MAX_PAYLOAD: usize = ffi::SRPC_BUF_SIZE as usize;
+
+/// The TCP port RPC servers listen on.
+pub const PORT: u16 = ffi::SRPC_PORT as u16;
+
+/// The overload control scheme an RPC server runs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Algorithm {
+    Breakwater,
+    Seda,
+    Dagor,
+    /// No overload control; every request is admitted.
+    NoControl,
+}
+impl Algorithm {
+    fn server_ops(self) -> &'static ffi::srpc_ops {
+        unsafe {
+            match self {
+                Algorithm::Breakwater => &*ptr::addr_of!(ffi::sbw_ops),
+                Algorithm::Seda => &*ptr::addr_of!(ffi::ssd_ops),
+                Algorithm::Dagor => &*ptr::addr_of!(ffi::sdg_ops),
+                Algorithm::NoControl => &*ptr::addr_of!(ffi::snc_ops),
+            }
+        }
+    }
+}
+/// Accepts the names netbench uses: `breakwater`, `seda`, `dagor` and
+/// `nocontrol`.
+impl FromStr for Algorithm {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> io::Result<Self> {
+        match s {
+            "breakwater" => Ok(Algorithm::Breakwater),
+            "seda" => Ok(Algorithm::Seda),
+            "dagor" => Ok(Algorithm::Dagor),
+            "nocontrol" => Ok(Algorithm::NoControl),
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unknown overload control algorithm: {}", s),
+            )),
+        }
+    }
+}
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Algorithm::Breakwater => "breakwater",
+            Algorithm::Seda => "seda",
+            Algorithm::Dagor => "dagor",
+            Algorithm::NoControl => "nocontrol",
+        })
+    }
+}
+
+/// The response to an RPC, written into the runtime's response buffer. Holds
+/// at most `MAX_PAYLOAD` bytes; writes past that are cut short.
+pub struct Response<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+impl<'a> Response<'a> {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Discards anything written so far.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+impl<'a> io::Write for Response<'a> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = cmp::min(buf.len(), self.buf.len() - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&buf[..n]);
+        self.len += n;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+type Handler = Box<dyn Fn(&[u8], &mut Response) + Send + Sync + 'static>;
+
+// srpc_fn_t carries no argument, so there is one handler per process, as
+// there is one server.
+static HANDLER: AtomicPtr<Handler> = AtomicPtr::new(0 as *mut Handler);
+
+extern "C" fn srpc_trampoline(ctx: *mut ffi::srpc_ctx) {
+    let handler = unsafe { &*HANDLER.load(Ordering::Acquire) };
+    let ctx = unsafe { &mut *ctx };
+    let req = unsafe {
+        slice::from_raw_parts(
+            ctx.req_buf.as_ptr() as *const u8,
+            cmp::min(ctx.req_len as usize, MAX_PAYLOAD),
+        )
+    };
+    let mut resp = Response {
+        buf: unsafe { slice::from_raw_parts_mut(ctx.resp_buf.as_mut_ptr() as *mut u8, MAX_PAYLOAD) },
+        len: 0,
+    };
+
+    // Unwinding into the RPC layer is undefined, so a panicking handler
+    // sends an empty response and is reported like a detached uthread.
+    if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(|| handler(req, &mut resp))) {
+        resp.clear();
+        thread::report_panic(payload);
+    }
+    ctx.resp_len = resp.len as _;
+}
+
+/// Counters kept by the running RPC server.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct ServerStats {
+    /// Requests received.
+    pub req_rx: u64,
+    /// Requests rejected by overload control.
+    pub req_dropped: u64,
+    /// Responses sent.
+    pub resp_tx: u64,
+    /// Credit (window) grants sent to clients.
+    pub win_tx: u64,
+}
+
+/// A running RPC server, from `Server::enable`.
+#[derive(Copy, Clone)]
+pub struct Server {
+    ops: &'static ffi::srpc_ops,
+}
+impl Server {
+    /// Starts the RPC server on `PORT`, running `handler` on its own uthread
+    /// for each admitted request. `handler` gets the request payload and
+    /// writes the response. Only one server can be enabled per process.
+    ///
+    /// ```ignore
+    /// let server = Server::enable(Algorithm::Breakwater, |req, resp| {
+    ///     resp.write_all(req).unwrap();
+    /// })?;
+    /// ```
+    pub fn enable<F>(algorithm: Algorithm, handler: F) -> io::Result<Server>
+    where
+        F: Fn(&[u8], &mut Response),
+        F: Send + Sync + 'static,
+    {
+        let new: *mut Handler = Box::into_raw(Box::new(Box::new(handler)));
+        if HANDLER
+            .compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire)
+            .is_err()
+        {
+            unsafe { drop(Box::from_raw(new)) };
+            return Err(io_error(-libc::EBUSY));
+        }
+
+        let ops = algorithm.server_ops();
+        let enable = ops.srpc_enable.expect("srpc_enable is not set");
+        match unsafe { enable(Some(srpc_trampoline)) } {
+            0 => Ok(Server { ops: ops }),
+            ret => {
+                // The RPC layer never saw the trampoline, so nothing else can
+                // hold the handler; release it so enable can be retried.
+                let old = HANDLER.swap(ptr::null_mut(), Ordering::AcqRel);
+                unsafe { drop(Box::from_raw(old)) };
+                Err(io_error(ret))
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ServerStats {
+        let stat = |f: Option<unsafe extern "C" fn() -> u64>| f.map_or(0, |f| unsafe { f() });
+        ServerStats {
+            req_rx: stat(self.ops.srpc_stat_req_rx),
+            req_dropped: stat(self.ops.srpc_stat_req_dropped),
+            resp_tx: stat(self.ops.srpc_stat_resp_tx),
+            win_tx: stat(self.ops.srpc_stat_win_tx),
+        }
+    }
+}
@@ -0,0 +1,455 @@
+use std::cell::UnsafeCell;
+use std::collections::VecDeque;
+use std::mem;
+use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+use std::time::{Duration, Instant};
+
+use super::*;
+use executor::Parker;
+use timer::Timeout;
+
+struct State<T> {
+    queue: VecDeque<T>,
+    cap: Option<usize>,
+    senders: usize,
+    receivers: usize,
+    /// Uthreads waiting for a message. All of them are woken on every send,
+    /// since some may be selecting and end up receiving elsewhere.
+    recv_waiters: Vec<Arc<Parker>>,
+    /// Uthreads waiting for room in a bounded channel. Each receive frees one
+    /// slot, so it wakes one of them.
+    send_waiters: VecDeque<Arc<Parker>>,
+}
+
+/// The state shared by both ends of a channel.
+struct Chan<T> {
+    lock: SpinLock,
+    state: UnsafeCell<State<T>>,
+}
+unsafe impl<T: Send> Send for Chan<T> {}
+unsafe impl<T: Send> Sync for Chan<T> {}
+
+fn wake_all<I: IntoIterator<Item = Arc<Parker>>>(waiters: I) {
+    for w in waiters {
+        w.unpark();
+    }
+}
+
+impl<T> Chan<T> {
+    fn new(cap: Option<usize>) -> Arc<Self> {
+        Arc::new(Chan {
+            lock: SpinLock::new(),
+            state: UnsafeCell::new(State {
+                queue: VecDeque::new(),
+                cap: cap,
+                senders: 1,
+                receivers: 1,
+                recv_waiters: Vec::new(),
+                send_waiters: VecDeque::new(),
+            }),
+        })
+    }
+
+    /// Runs `f` with the channel locked. `f` must not block or panic.
+    fn with<R, F: FnOnce(&mut State<T>) -> R>(&self, f: F) -> R {
+        self.lock.lock_np();
+        let r = f(unsafe { &mut *self.state.get() });
+        self.lock.unlock_np();
+        r
+    }
+
+    /// Sends without blocking. If the channel is full and `waiter` is given,
+    /// it is registered to be woken once there is room.
+    fn try_send(&self, t: T, waiter: Option<&Arc<Parker>>) -> Result<(), TrySendError<T>> {
+        let res = self.with(|s| {
+            if s.receivers == 0 {
+                return Err(TrySendError::Disconnected(t));
+            }
+            if let Some(cap) = s.cap {
+                if s.queue.len() >= cap {
+                    if let Some(w) = waiter {
+                        if !s.send_waiters.iter().any(|x| Arc::ptr_eq(x, w)) {
+                            s.send_waiters.push_back(w.clone());
+                        }
+                    }
+                    return Err(TrySendError::Full(t));
+                }
+            }
+            s.queue.push_back(t);
+            Ok(mem::replace(&mut s.recv_waiters, Vec::new()))
+        });
+        res.map(wake_all)
+    }
+
+    /// Receives without blocking. If the channel is empty and `waiter` is
+    /// given, it is registered to be woken by the next send.
+    fn try_recv(&self, waiter: Option<&Arc<Parker>>) -> Result<T, TryRecvError> {
+        let res = self.with(|s| match s.queue.pop_front() {
+            Some(t) => Ok((t, s.send_waiters.pop_front())),
+            None if s.senders == 0 => Err(TryRecvError::Disconnected),
+            None => {
+                if let Some(w) = waiter {
+                    if !s.recv_waiters.iter().any(|x| Arc::ptr_eq(x, w)) {
+                        s.recv_waiters.push(w.clone());
+                    }
+                }
+                Err(TryRecvError::Empty)
+            }
+        });
+        res.map(|(t, sender)| {
+            wake_all(sender);
+            t
+        })
+    }
+
+    fn send(&self, t: T) -> Result<(), SendError<T>> {
+        let mut t = match self.try_send(t, None) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Disconnected(t)) => return Err(SendError(t)),
+            Err(TrySendError::Full(t)) => t,
+        };
+
+        let parker = Arc::new(Parker::new());
+        let res = loop {
+            t = match self.try_send(t, Some(&parker)) {
+                Ok(()) => break Ok(()),
+                Err(TrySendError::Disconnected(t)) => break Err(SendError(t)),
+                Err(TrySendError::Full(t)) => t,
+            };
+            parker.park();
+        };
+        self.with(|s| s.send_waiters.retain(|w| !Arc::ptr_eq(w, &parker)));
+        res
+    }
+
+    fn recv(&self, deadline: Option<Instant>) -> Result<T, RecvTimeoutError> {
+        match self.try_recv(None) {
+            Ok(t) => return Ok(t),
+            Err(TryRecvError::Disconnected) => return Err(RecvTimeoutError::Disconnected),
+            Err(TryRecvError::Empty) => (),
+        }
+
+        let parker = Arc::new(Parker::new());
+        let timeout = deadline.map(|d| Timeout::new(d, &parker));
+        let res = loop {
+            match self.try_recv(Some(&parker)) {
+                Ok(t) => break Ok(t),
+                Err(TryRecvError::Disconnected) => break Err(RecvTimeoutError::Disconnected),
+                Err(TryRecvError::Empty) => (),
+            }
+            if timeout.as_ref().map_or(false, |t| t.expired()) {
+                break Err(RecvTimeoutError::Timeout);
+            }
+            parker.park();
+        };
+        self.with(|s| s.recv_waiters.retain(|w| !Arc::ptr_eq(w, &parker)));
+        res
+    }
+
+    fn add_sender(&self) {
+        self.with(|s| s.senders += 1);
+    }
+
+    fn drop_sender(&self) {
+        let waiters = self.with(|s| {
+            s.senders -= 1;
+            if s.senders == 0 {
+                mem::replace(&mut s.recv_waiters, Vec::new())
+            } else {
+                Vec::new()
+            }
+        });
+        wake_all(waiters);
+    }
+
+    fn add_receiver(&self) {
+        self.with(|s| s.receivers += 1);
+    }
+
+    fn drop_receiver(&self) {
+        let waiters = self.with(|s| {
+            s.receivers -= 1;
+            if s.receivers == 0 {
+                mem::replace(&mut s.send_waiters, VecDeque::new())
+            } else {
+                VecDeque::new()
+            }
+        });
+        wake_all(waiters);
+    }
+}
+
+/// Creates an unbounded multi-producer, single-consumer channel. Sends never
+/// block; receivers park their uthread until a message arrives.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let chan = Chan::new(None);
+    (Sender { chan: chan.clone() }, Receiver { chan: chan })
+}
+
+/// Creates a multi-producer, multi-consumer channel that holds at most
+/// `bound` messages. Senders park while it is full. Unlike
+/// `std::sync::mpsc::sync_channel`, `bound` must be non-zero.
+pub fn sync_channel<T>(bound: usize) -> (SyncSender<T>, SyncReceiver<T>) {
+    assert!(bound > 0, "sync_channel bound must be non-zero");
+    let chan = Chan::new(Some(bound));
+    (SyncSender { chan: chan.clone() }, SyncReceiver { chan: chan })
+}
+
+/// The sending half of a `channel`.
+pub struct Sender<T> {
+    chan: Arc<Chan<T>>,
+}
+impl<T> Sender<T> {
+    /// Queues `t`, failing only if the receiver has been dropped.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        self.chan.try_send(t, None).map_err(|e| match e {
+            TrySendError::Full(t) | TrySendError::Disconnected(t) => SendError(t),
+        })
+    }
+}
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        Sender {
+            chan: self.chan.clone(),
+        }
+    }
+}
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// The sending half of a `sync_channel`.
+pub struct SyncSender<T> {
+    chan: Arc<Chan<T>>,
+}
+impl<T> SyncSender<T> {
+    /// Queues `t`, parking while the channel is full. Fails if every receiver
+    /// has been dropped.
+    pub fn send(&self, t: T) -> Result<(), SendError<T>> {
+        self.chan.send(t)
+    }
+
+    pub fn try_send(&self, t: T) -> Result<(), TrySendError<T>> {
+        self.chan.try_send(t, None)
+    }
+}
+impl<T> Clone for SyncSender<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_sender();
+        SyncSender {
+            chan: self.chan.clone(),
+        }
+    }
+}
+impl<T> Drop for SyncSender<T> {
+    fn drop(&mut self) {
+        self.chan.drop_sender();
+    }
+}
+
+/// The receiving half of a `channel`.
+pub struct Receiver<T> {
+    chan: Arc<Chan<T>>,
+}
+impl<T> Receiver<T> {
+    /// Parks until a message arrives. Fails once every sender has been
+    /// dropped and the queue is drained.
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.chan.recv(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.chan.try_recv(None)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.chan.recv(Some(Instant::now() + timeout))
+    }
+
+    /// Iterates over messages until every sender has been dropped.
+    pub fn iter(&self) -> Iter<T> {
+        Iter { chan: &self.chan }
+    }
+}
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.chan.drop_receiver();
+    }
+}
+
+/// The receiving half of a `sync_channel`. Clones share the same queue, and
+/// each message goes to exactly one of them.
+pub struct SyncReceiver<T> {
+    chan: Arc<Chan<T>>,
+}
+impl<T> SyncReceiver<T> {
+    pub fn recv(&self) -> Result<T, RecvError> {
+        self.chan.recv(None).map_err(|_| RecvError)
+    }
+
+    pub fn try_recv(&self) -> Result<T, TryRecvError> {
+        self.chan.try_recv(None)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.chan.recv(Some(Instant::now() + timeout))
+    }
+
+    pub fn iter(&self) -> Iter<T> {
+        Iter { chan: &self.chan }
+    }
+}
+impl<T> Clone for SyncReceiver<T> {
+    fn clone(&self) -> Self {
+        self.chan.add_receiver();
+        SyncReceiver {
+            chan: self.chan.clone(),
+        }
+    }
+}
+impl<T> Drop for SyncReceiver<T> {
+    fn drop(&mut self) {
+        self.chan.drop_receiver();
+    }
+}
+
+/// Blocking iterator over a receiver's messages. See `Receiver::iter`.
+pub struct Iter<'a, T: 'a> {
+    chan: &'a Chan<T>,
+}
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.chan.recv(None).ok()
+    }
+}
+
+/// The readiness check `Select` performs on each receiver.
+trait Ready {
+    /// Returns true if a receive would not block. Otherwise registers
+    /// `waiter` to be woken when that changes.
+    fn ready(&self, waiter: &Arc<Parker>) -> bool;
+    fn unregister(&self, waiter: &Arc<Parker>);
+}
+impl<T> Ready for Chan<T> {
+    fn ready(&self, waiter: &Arc<Parker>) -> bool {
+        self.with(|s| {
+            if !s.queue.is_empty() || s.senders == 0 {
+                return true;
+            }
+            if !s.recv_waiters.iter().any(|x| Arc::ptr_eq(x, waiter)) {
+                s.recv_waiters.push(waiter.clone());
+            }
+            false
+        })
+    }
+
+    fn unregister(&self, waiter: &Arc<Parker>) {
+        self.with(|s| s.recv_waiters.retain(|w| !Arc::ptr_eq(w, waiter)));
+    }
+}
+
+/// An opaque reference to a receiver, for `Select`.
+pub struct SelectHandle<'a> {
+    chan: &'a dyn Ready,
+}
+
+/// Implemented by the receiving halves of channels, so that a `Select` can
+/// wait on them.
+pub trait Selectable {
+    fn select_handle(&self) -> SelectHandle;
+}
+impl<T> Selectable for Receiver<T> {
+    fn select_handle(&self) -> SelectHandle {
+        SelectHandle { chan: &*self.chan }
+    }
+}
+impl<T> Selectable for SyncReceiver<T> {
+    fn select_handle(&self) -> SelectHandle {
+        SelectHandle { chan: &*self.chan }
+    }
+}
+
+/// Waits on several receivers at once, e.g.
+///
+/// ```ignore
+/// let mut sel = Select::new();
+/// let reqs = sel.recv(&req_rx);
+/// let ctl = sel.recv(&ctl_rx);
+/// match sel.ready() {
+///     i if i == reqs => handle(req_rx.try_recv()),
+///     _ => control(ctl_rx.try_recv()),
+/// }
+/// ```
+///
+/// Readiness means that a receive would not block, either because a message
+/// is queued or because every sender is gone. A `SyncReceiver` may be raced by
+/// its clones, so its `try_recv` can still come back empty.
+pub struct Select<'a> {
+    handles: Vec<SelectHandle<'a>>,
+}
+impl<'a> Select<'a> {
+    pub fn new() -> Self {
+        Select {
+            handles: Vec::new(),
+        }
+    }
+
+    /// Adds a receiver and returns the index `ready` reports for it.
+    pub fn recv<S: Selectable>(&mut self, receiver: &'a S) -> usize {
+        self.handles.push(receiver.select_handle());
+        self.handles.len() - 1
+    }
+
+    /// Returns the index of a ready receiver without blocking.
+    pub fn try_ready(&self) -> Option<usize> {
+        // An unpublished parker collects registrations that are undone below.
+        let parker = Arc::new(Parker::new());
+        let res = self.handles.iter().position(|h| h.chan.ready(&parker));
+        self.unregister(&parker);
+        res
+    }
+
+    /// Parks until one of the receivers is ready and returns its index.
+    pub fn ready(&self) -> usize {
+        self.wait(None).unwrap()
+    }
+
+    /// Like `ready`, but gives up and returns `None` after `timeout`.
+    pub fn ready_timeout(&self, timeout: Duration) -> Option<usize> {
+        self.wait(Some(Instant::now() + timeout))
+    }
+
+    fn wait(&self, deadline: Option<Instant>) -> Option<usize> {
+        assert!(!self.handles.is_empty(), "select with no receivers");
+
+        let parker = Arc::new(Parker::new());
+        let timeout = deadline.map(|d| Timeout::new(d, &parker));
+        let res = loop {
+            if let Some(i) = self.handles.iter().position(|h| h.chan.ready(&parker)) {
+                break Some(i);
+            }
+            if timeout.as_ref().map_or(false, |t| t.expired()) {
+                break None;
+            }
+            parker.park();
+        };
+        self.unregister(&parker);
+        res
+    }
+
+    fn unregister(&self, parker: &Arc<Parker>) {
+        for h in &self.handles {
+            h.chan.unregister(parker);
+        }
+    }
+}
+impl<'a> Default for Select<'a> {
+    fn default() -> Self {
+        Select::new()
+    }
+}
@@ -0,0 +1,440 @@
+use std::error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+use std::path::Path;
+use std::time::Duration;
+use std::{env, process};
+
+use super::*;
+
+/// Limits enforced by the runtime's config parser (runtime/cfg.c).
+const MAX_KTHREADS: u32 = 256;
+const MAX_MTU: u32 = 9000;
+const MAX_STATIC_ARP_ENTRIES: usize = 1024;
+const MAX_LOG_LEVEL: u8 = 6;
+
+/// How the IOKernel schedules the runtime's cores.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Priority {
+    LatencyCritical,
+    BestEffort,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    msg: String,
+}
+impl ConfigError {
+    fn new<S: Into<String>>(msg: S) -> Self {
+        ConfigError { msg: msg.into() }
+    }
+}
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid runtime config: {}", self.msg)
+    }
+}
+impl error::Error for ConfigError {}
+impl From<ConfigError> for io::Error {
+    fn from(e: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, e)
+    }
+}
+
+/// A validated runtime configuration, rendered (through `Display` or
+/// `write_to`) in the format `runtime_init` expects.
+///
+/// ```ignore
+/// let cfg = RuntimeConfig::builder(addr, netmask, gateway, 4)
+///     .guaranteed_kthreads(2)
+///     .priority(Priority::LatencyCritical)
+///     .build()?;
+/// cfg.write_to("server.config")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    host_addr: Ipv4Addr,
+    host_netmask: Ipv4Addr,
+    host_gateway: Ipv4Addr,
+    host_mac: Option<[u8; 6]>,
+    host_mtu: Option<u32>,
+    kthreads: u32,
+    spinning_kthreads: u32,
+    guaranteed_kthreads: u32,
+    priority: Option<Priority>,
+    ht_punish: Option<Duration>,
+    qdelay: Option<Duration>,
+    static_arp: Vec<(Ipv4Addr, [u8; 6])>,
+    log_level: Option<u8>,
+    disable_watchdog: bool,
+    preferred_socket: Option<u32>,
+    enable_storage: bool,
+    enable_directpath: bool,
+}
+impl RuntimeConfig {
+    /// Starts a config from the options the runtime requires.
+    pub fn builder(
+        host_addr: Ipv4Addr,
+        host_netmask: Ipv4Addr,
+        host_gateway: Ipv4Addr,
+        kthreads: u32,
+    ) -> RuntimeConfigBuilder {
+        RuntimeConfigBuilder {
+            cfg: RuntimeConfig {
+                host_addr: host_addr,
+                host_netmask: host_netmask,
+                host_gateway: host_gateway,
+                host_mac: None,
+                host_mtu: None,
+                kthreads: kthreads,
+                spinning_kthreads: 0,
+                guaranteed_kthreads: 0,
+                priority: None,
+                ht_punish: None,
+                qdelay: None,
+                static_arp: Vec::new(),
+                log_level: None,
+                disable_watchdog: false,
+                preferred_socket: None,
+                enable_storage: false,
+                enable_directpath: false,
+            },
+        }
+    }
+
+    pub fn host_addr(&self) -> Ipv4Addr {
+        self.host_addr
+    }
+
+    pub fn kthreads(&self) -> u32 {
+        self.kthreads
+    }
+
+    pub fn guaranteed_kthreads(&self) -> u32 {
+        self.guaranteed_kthreads
+    }
+
+    pub fn write_to<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut f = File::create(path)?;
+        write!(f, "{}", self)?;
+        f.flush()
+    }
+
+    /// Validates the config and renders it into an already unlinked file,
+    /// which the runtime can read through `/proc/self/fd`.
+    pub(crate) fn to_temp_file(&self) -> io::Result<File> {
+        self.validate()?;
+        let path = env::temp_dir().join(format!("shenango-{}.config", process::id()));
+        let mut f = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        fs::remove_file(&path)?;
+        write!(f, "{}", self)?;
+        f.flush()?;
+        Ok(f)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let addr = u32::from(self.host_addr);
+        let mask = u32::from(self.host_netmask);
+        let gateway = u32::from(self.host_gateway);
+
+        if mask == 0 || (!mask).wrapping_add(1) & !mask != 0 {
+            return Err(ConfigError::new(format!(
+                "netmask {} is not a contiguous prefix",
+                self.host_netmask
+            )));
+        }
+        if self.host_addr.is_loopback() || self.host_gateway.is_loopback() {
+            return Err(ConfigError::new("addresses can't be in the loopback subnet"));
+        }
+        if addr & mask != gateway & mask {
+            return Err(ConfigError::new(format!(
+                "gateway {} is outside the host subnet {}/{}",
+                self.host_gateway,
+                Ipv4Addr::from(addr & mask),
+                mask.count_ones()
+            )));
+        }
+        if addr == gateway {
+            return Err(ConfigError::new("host address and gateway are the same"));
+        }
+        // /31 and /32 networks have no network or broadcast address.
+        if mask.count_ones() < 31 && (addr & !mask == 0 || addr & !mask == !mask) {
+            return Err(ConfigError::new(format!(
+                "host address {} is the network or broadcast address",
+                self.host_addr
+            )));
+        }
+
+        if self.kthreads < 1 || self.kthreads >= MAX_KTHREADS {
+            return Err(ConfigError::new(format!(
+                "kthreads must be > 0 and < {}, got {}",
+                MAX_KTHREADS, self.kthreads
+            )));
+        }
+        if self.guaranteed_kthreads > self.kthreads {
+            return Err(ConfigError::new(format!(
+                "guaranteed kthreads ({}) must be <= kthreads ({})",
+                self.guaranteed_kthreads, self.kthreads
+            )));
+        }
+        if self.spinning_kthreads > self.kthreads {
+            return Err(ConfigError::new(format!(
+                "spinning kthreads ({}) must be <= kthreads ({})",
+                self.spinning_kthreads, self.kthreads
+            )));
+        }
+
+        if let Some(mtu) = self.host_mtu {
+            if mtu > MAX_MTU {
+                return Err(ConfigError::new(format!(
+                    "MTU must be <= {}, got {}",
+                    MAX_MTU, mtu
+                )));
+            }
+        }
+        if self.static_arp.len() > MAX_STATIC_ARP_ENTRIES {
+            return Err(ConfigError::new(format!(
+                "at most {} static ARP entries are supported",
+                MAX_STATIC_ARP_ENTRIES
+            )));
+        }
+        if let Some(level) = self.log_level {
+            if level > MAX_LOG_LEVEL {
+                return Err(ConfigError::new(format!(
+                    "log level must be between 0 and {}, got {}",
+                    MAX_LOG_LEVEL, level
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn fmt_mac(f: &mut fmt::Formatter, mac: &[u8; 6]) -> fmt::Result {
+    write!(
+        f,
+        "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+        mac[0], mac[1], mac[2], mac[3], mac[4], mac[5]
+    )
+}
+
+impl fmt::Display for RuntimeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "host_addr {}", self.host_addr)?;
+        writeln!(f, "host_netmask {}", self.host_netmask)?;
+        writeln!(f, "host_gateway {}", self.host_gateway)?;
+        if let Some(ref mac) = self.host_mac {
+            write!(f, "host_mac ")?;
+            fmt_mac(f, mac)?;
+            writeln!(f)?;
+        }
+        if let Some(mtu) = self.host_mtu {
+            writeln!(f, "host_mtu {}", mtu)?;
+        }
+        writeln!(f, "runtime_kthreads {}", self.kthreads)?;
+        writeln!(f, "runtime_spinning_kthreads {}", self.spinning_kthreads)?;
+        writeln!(f, "runtime_guaranteed_kthreads {}", self.guaranteed_kthreads)?;
+        match self.priority {
+            Some(Priority::LatencyCritical) => writeln!(f, "runtime_priority lc")?,
+            Some(Priority::BestEffort) => writeln!(f, "runtime_priority be")?,
+            None => {}
+        }
+        if let Some(d) = self.ht_punish {
+            writeln!(f, "runtime_ht_punish_us {}", timer::duration_to_us(d))?;
+        }
+        if let Some(d) = self.qdelay {
+            writeln!(f, "runtime_qdelay_us {}", timer::duration_to_us(d))?;
+        }
+        for &(ip, ref mac) in &self.static_arp {
+            write!(f, "static_arp {} ", ip)?;
+            fmt_mac(f, mac)?;
+            writeln!(f)?;
+        }
+        if let Some(level) = self.log_level {
+            writeln!(f, "log_level {}", level)?;
+        }
+        // The parser expects a value even for flag options.
+        if self.disable_watchdog {
+            writeln!(f, "disable_watchdog 1")?;
+        }
+        if let Some(socket) = self.preferred_socket {
+            writeln!(f, "preferred_socket {}", socket)?;
+        }
+        if self.enable_storage {
+            writeln!(f, "enable_storage 1")?;
+        }
+        if self.enable_directpath {
+            writeln!(f, "enable_directpath 1")?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `RuntimeConfig`. Options left unset use the runtime's defaults.
+#[derive(Debug, Clone)]
+pub struct RuntimeConfigBuilder {
+    cfg: RuntimeConfig,
+}
+impl RuntimeConfigBuilder {
+    pub fn mac(mut self, mac: [u8; 6]) -> Self {
+        self.cfg.host_mac = Some(mac);
+        self
+    }
+
+    pub fn mtu(mut self, mtu: u32) -> Self {
+        self.cfg.host_mtu = Some(mtu);
+        self
+    }
+
+    pub fn spinning_kthreads(mut self, n: u32) -> Self {
+        self.cfg.spinning_kthreads = n;
+        self
+    }
+
+    pub fn guaranteed_kthreads(mut self, n: u32) -> Self {
+        self.cfg.guaranteed_kthreads = n;
+        self
+    }
+
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.cfg.priority = Some(priority);
+        self
+    }
+
+    pub fn ht_punish(mut self, d: Duration) -> Self {
+        self.cfg.ht_punish = Some(d);
+        self
+    }
+
+    /// The queueing delay above which the IOKernel grants another core.
+    pub fn qdelay(mut self, d: Duration) -> Self {
+        self.cfg.qdelay = Some(d);
+        self
+    }
+
+    pub fn static_arp(mut self, ip: Ipv4Addr, mac: [u8; 6]) -> Self {
+        self.cfg.static_arp.push((ip, mac));
+        self
+    }
+
+    pub fn log_level(mut self, level: u8) -> Self {
+        self.cfg.log_level = Some(level);
+        self
+    }
+
+    pub fn disable_watchdog(mut self, disable: bool) -> Self {
+        self.cfg.disable_watchdog = disable;
+        self
+    }
+
+    pub fn preferred_socket(mut self, socket: u32) -> Self {
+        self.cfg.preferred_socket = Some(socket);
+        self
+    }
+
+    /// Requires a runtime built with storage support.
+    pub fn enable_storage(mut self, enable: bool) -> Self {
+        self.cfg.enable_storage = enable;
+        self
+    }
+
+    /// Requires a runtime built with directpath support.
+    pub fn enable_directpath(mut self, enable: bool) -> Self {
+        self.cfg.enable_directpath = enable;
+        self
+    }
+
+    pub fn build(self) -> Result<RuntimeConfig, ConfigError> {
+        self.cfg.validate()?;
+        Ok(self.cfg)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn base() -> RuntimeConfigBuilder {
+        RuntimeConfig::builder(
+            Ipv4Addr::new(192, 168, 1, 5),
+            Ipv4Addr::new(255, 255, 255, 0),
+            Ipv4Addr::new(192, 168, 1, 1),
+            3,
+        )
+    }
+
+    #[test]
+    fn render() {
+        let cfg = base()
+            .priority(Priority::BestEffort)
+            .static_arp(Ipv4Addr::new(192, 168, 1, 7), [0x90, 0xe2, 0xba, 0, 0x0a, 1])
+            .disable_watchdog(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            cfg.to_string(),
+            "host_addr 192.168.1.5\n\
+             host_netmask 255.255.255.0\n\
+             host_gateway 192.168.1.1\n\
+             runtime_kthreads 3\n\
+             runtime_spinning_kthreads 0\n\
+             runtime_guaranteed_kthreads 0\n\
+             runtime_priority be\n\
+             static_arp 192.168.1.7 90:e2:ba:00:0a:01\n\
+             disable_watchdog 1\n"
+        );
+    }
+
+    #[test]
+    fn validate() {
+        assert!(base().build().is_ok());
+        assert!(base().guaranteed_kthreads(4).build().is_err());
+        assert!(base().mtu(9001).build().is_err());
+
+        let bad_mask = RuntimeConfig::builder(
+            Ipv4Addr::new(192, 168, 1, 5),
+            Ipv4Addr::new(255, 0, 255, 0),
+            Ipv4Addr::new(192, 168, 1, 1),
+            3,
+        );
+        assert!(bad_mask.build().is_err());
+
+        let bad_gateway = RuntimeConfig::builder(
+            Ipv4Addr::new(192, 168, 1, 5),
+            Ipv4Addr::new(255, 255, 255, 0),
+            Ipv4Addr::new(192, 168, 2, 1),
+            3,
+        );
+        assert!(bad_gateway.build().is_err());
+
+        let no_kthreads = RuntimeConfig::builder(
+            Ipv4Addr::new(192, 168, 1, 5),
+            Ipv4Addr::new(255, 255, 255, 0),
+            Ipv4Addr::new(192, 168, 1, 1),
+            0,
+        );
+        assert!(no_kthreads.build().is_err());
+    }
+
+    #[test]
+    fn temp_file() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let cfg = base().build().unwrap();
+        let mut f = cfg.to_temp_file().unwrap();
+        let mut rendered = String::new();
+        f.seek(SeekFrom::Start(0)).unwrap();
+        f.read_to_string(&mut rendered).unwrap();
+        assert_eq!(rendered, cfg.to_string());
+
+        // nothing is left behind once the file is closed
+        let path = env::temp_dir().join(format!("shenango-{}.config", process::id()));
+        assert!(!path.exists());
+    }
+}
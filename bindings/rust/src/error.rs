@@ -0,0 +1,140 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+use runtime::InitStage;
+
+/// An error from the runtime.
+///
+/// The runtime reports failures as negative errnos; they are normalized here,
+/// so `errno` is always positive. I/O calls return OS `io::Error`s, so
+/// `raw_os_error` gives back the errno, except for the few errnos whose OS
+/// kind is uncategorized: those wrap an `Error` with a meaningful kind
+/// instead. `io_errno` recovers the errno in either case.
+#[derive(Debug)]
+pub enum Error {
+    Errno(i32),
+    /// Starting the runtime failed at `stage`, with the runtime's error or
+    /// the one returned by a user hook.
+    Init(InitStage, io::Error),
+}
+impl Error {
+    /// Accepts either sign of errno.
+    pub fn from_errno(errno: i32) -> Self {
+        Error::Errno(errno.wrapping_abs())
+    }
+
+    pub fn errno(&self) -> Option<i32> {
+        match *self {
+            Error::Errno(errno) => Some(errno),
+            Error::Init(_, ref e) => io_errno(e),
+        }
+    }
+
+    pub fn init_stage(&self) -> Option<InitStage> {
+        match *self {
+            Error::Errno(_) => None,
+            Error::Init(stage, _) => Some(stage),
+        }
+    }
+
+    pub fn kind(&self) -> io::ErrorKind {
+        match *self {
+            Error::Errno(errno) => {
+                errno_kind(errno).unwrap_or_else(|| io::Error::from_raw_os_error(errno).kind())
+            }
+            Error::Init(_, ref e) => e.kind(),
+        }
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Errno(errno) => io::Error::from_raw_os_error(errno).fmt(f),
+            Error::Init(InitStage::Runtime, ref e) => write!(f, "runtime init failed: {}", e),
+            Error::Init(InitStage::Global, ref e) => write!(f, "global initializer failed: {}", e),
+            Error::Init(InitStage::Kthread, ref e) => {
+                write!(f, "per-kthread initializer failed: {}", e)
+            }
+            Error::Init(InitStage::Late, ref e) => write!(f, "late initializer failed: {}", e),
+        }
+    }
+}
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Errno(_) => None,
+            Error::Init(_, ref e) => Some(e),
+        }
+    }
+}
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Errno(errno) if errno_kind(errno).is_none() => {
+                io::Error::from_raw_os_error(errno)
+            }
+            e => io::Error::new(e.kind(), e),
+        }
+    }
+}
+
+/// Kinds for errnos that the OS mapping leaves uncategorized.
+fn errno_kind(errno: i32) -> Option<io::ErrorKind> {
+    match errno {
+        libc::ENOBUFS => Some(io::ErrorKind::OutOfMemory),
+        _ => None,
+    }
+}
+
+/// Converts a negative runtime return code into an `io::Error`.
+pub(crate) fn io_error(ret: i32) -> io::Error {
+    Error::from_errno(ret).into()
+}
+
+/// Recovers the errno behind an `io::Error`, whether it came from the
+/// runtime or from the OS.
+pub fn io_errno(e: &io::Error) -> Option<i32> {
+    e.raw_os_error().or_else(|| {
+        e.get_ref()
+            .and_then(|inner| inner.downcast_ref::<Error>())
+            .and_then(Error::errno)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libc;
+
+    #[test]
+    fn normalize() {
+        let e = Error::from_errno(-libc::ECONNRESET);
+        assert_eq!(e.errno(), Some(libc::ECONNRESET));
+        assert_eq!(e.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(Error::from_errno(libc::ECONNRESET).errno(), Some(libc::ECONNRESET));
+
+        let e = io_error(-libc::ENOBUFS);
+        assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(io_errno(&e), Some(libc::ENOBUFS));
+        let e = io_error(-libc::ENOMEM);
+        assert_eq!(e.kind(), io::ErrorKind::OutOfMemory);
+        assert_eq!(e.raw_os_error(), Some(libc::ENOMEM));
+        assert_eq!(io_error(-libc::EAGAIN).kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(io_error(-libc::ECONNABORTED).kind(), io::ErrorKind::ConnectionAborted);
+
+        let e: io::Error = Error::from_errno(-libc::ETIMEDOUT).into();
+        assert_eq!(e.raw_os_error(), Some(libc::ETIMEDOUT));
+        assert_eq!(e.kind(), io::ErrorKind::TimedOut);
+    }
+
+    #[test]
+    fn init_stage() {
+        let e = Error::Init(InitStage::Kthread, io_error(-libc::ENOMEM));
+        assert_eq!(e.init_stage(), Some(InitStage::Kthread));
+        assert_eq!(e.errno(), Some(libc::ENOMEM));
+        let e = Error::Init(InitStage::Runtime, io_error(-libc::ENOBUFS));
+        assert_eq!(e.errno(), Some(libc::ENOBUFS));
+        assert_eq!(Error::from_errno(libc::EINVAL).init_stage(), None);
+    }
+}
@@ -0,0 +1,349 @@
+use std::any::Any;
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+use std::{mem, panic, ptr};
+
+use super::*;
+use poll::Pollable;
+
+/// Wakes a uthread parked in `block_on` (or on a channel). A wake that
+/// arrives before the uthread parks is remembered, so it is never lost.
+pub(crate) struct Parker {
+    lock: SpinLock,
+    waiter: UnsafeCell<*mut ffi::thread_t>,
+    notified: UnsafeCell<bool>,
+}
+impl Parker {
+    pub(crate) fn new() -> Self {
+        Parker {
+            lock: SpinLock::new(),
+            waiter: UnsafeCell::new(ptr::null_mut()),
+            notified: UnsafeCell::new(false),
+        }
+    }
+
+    pub(crate) fn park(&self) {
+        self.lock.lock_np();
+        unsafe {
+            if mem::replace(&mut *self.notified.get(), false) {
+                self.lock.unlock_np();
+                return;
+            }
+            *self.waiter.get() = thread::thread_self();
+            ffi::thread_park_and_unlock_np(self.lock.as_raw());
+        }
+    }
+
+    pub(crate) fn unpark(&self) {
+        self.lock.lock_np();
+        let waiter = unsafe { mem::replace(&mut *self.waiter.get(), ptr::null_mut()) };
+        if waiter.is_null() {
+            unsafe { *self.notified.get() = true };
+        }
+        self.lock.unlock_np();
+
+        if !waiter.is_null() {
+            unsafe { ffi::thread_ready(waiter) };
+        }
+    }
+}
+impl Wake for Parker {
+    fn wake(self: Arc<Self>) {
+        self.unpark()
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.unpark()
+    }
+}
+unsafe impl Send for Parker {}
+unsafe impl Sync for Parker {}
+
+/// A one-bit event shared between futures and whatever completes them.
+/// Every task waiting on it is woken by `notify`; the first to poll again
+/// consumes the event and the rest wait for the next one.
+pub(crate) struct Signal {
+    lock: SpinLock,
+    set: UnsafeCell<bool>,
+    wakers: UnsafeCell<Vec<Waker>>,
+}
+impl Signal {
+    pub(crate) fn new() -> Self {
+        Signal {
+            lock: SpinLock::new(),
+            set: UnsafeCell::new(false),
+            wakers: UnsafeCell::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn notify(&self) {
+        self.lock.lock_np();
+        let wakers = unsafe {
+            *self.set.get() = true;
+            mem::replace(&mut *self.wakers.get(), Vec::new())
+        };
+        self.lock.unlock_np();
+
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+
+    /// Consumes the event if it is set, otherwise registers `cx` to be woken
+    /// by the next `notify`.
+    pub(crate) fn poll_take(&self, cx: &mut Context) -> Poll<()> {
+        self.lock.lock_np();
+        let set = unsafe { mem::replace(&mut *self.set.get(), false) };
+        if !set {
+            let wakers = unsafe { &mut *self.wakers.get() };
+            if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
+        }
+        self.lock.unlock_np();
+
+        if set {
+            Poll::Ready(())
+        } else {
+            Poll::Pending
+        }
+    }
+}
+unsafe impl Send for Signal {}
+unsafe impl Sync for Signal {}
+
+/// Runs a future to completion on the calling uthread, parking it whenever
+/// the future is pending.
+pub fn block_on<F: Future>(mut f: F) -> F::Output {
+    let parker = Arc::new(Parker::new());
+    let waker = Waker::from(parker.clone());
+    let mut cx = Context::from_waker(&waker);
+
+    // The future lives on this stack frame until we return, so it never moves.
+    let mut f = unsafe { Pin::new_unchecked(&mut f) };
+    loop {
+        if let Poll::Ready(output) = f.as_mut().poll(&mut cx) {
+            return output;
+        }
+        parker.park();
+    }
+}
+
+struct Task<T> {
+    done: Signal,
+    result: UnsafeCell<Option<Result<T, Box<dyn Any + Send + 'static>>>>,
+}
+unsafe impl<T: Send> Send for Task<T> {}
+unsafe impl<T: Send> Sync for Task<T> {}
+
+/// Spawns a future onto its own uthread. The returned handle can be awaited,
+/// or joined from blocking code with `join`.
+pub fn spawn_async<F>(f: F) -> JoinHandle<F::Output>
+where
+    F: Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let task = Arc::new(Task {
+        done: Signal::new(),
+        result: UnsafeCell::new(None),
+    });
+
+    let t = task.clone();
+    thread::spawn_detached(move || {
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(move || block_on(f)));
+        unsafe { *t.result.get() = Some(result) };
+        t.done.notify();
+    });
+    JoinHandle { task }
+}
+
+/// Handle to a future started with `spawn_async`. Resolves to the future's
+/// output, or to the panic payload if it panicked. Dropping the handle lets
+/// the task run to completion unobserved.
+pub struct JoinHandle<T> {
+    task: Arc<Task<T>>,
+}
+impl<T> JoinHandle<T> {
+    pub fn join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        block_on(self)
+    }
+}
+impl<T> Future for JoinHandle<T> {
+    type Output = Result<T, Box<dyn Any + Send + 'static>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.task.done.poll_take(cx) {
+            Poll::Ready(()) => Poll::Ready(
+                unsafe { (*self.task.result.get()).take() }.expect("JoinHandle polled after completion"),
+            ),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Turns poll triggers into wakeups. A single reactor uthread waits on every
+/// registered source and notifies the matching `Signal`.
+struct Reactor {
+    poller: poll::Poller,
+    sources: sync::Mutex<HashMap<usize, Arc<Signal>>>,
+    next_token: AtomicUsize,
+}
+impl Reactor {
+    fn run(&self) {
+        loop {
+            let token = self.poller.wait();
+            let signal = self.sources.lock().get(&token).cloned();
+            if let Some(signal) = signal {
+                signal.notify();
+            }
+        }
+    }
+}
+// Only the reactor uthread calls `poller.wait`.
+unsafe impl Sync for Reactor {}
+
+static REACTOR: AtomicPtr<Reactor> = AtomicPtr::new(0 as *mut Reactor);
+
+fn reactor() -> &'static Reactor {
+    let r = REACTOR.load(Ordering::Acquire);
+    if !r.is_null() {
+        return unsafe { &*r };
+    }
+
+    let new = Box::into_raw(Box::new(Reactor {
+        poller: poll::Poller::new(),
+        sources: sync::Mutex::new(HashMap::new()),
+        next_token: AtomicUsize::new(0),
+    }));
+    match REACTOR.compare_exchange(ptr::null_mut(), new, Ordering::AcqRel, Ordering::Acquire) {
+        Ok(_) => {
+            let r: &'static Reactor = unsafe { &*new };
+            thread::spawn_detached(move || r.run());
+            r
+        }
+        Err(existing) => {
+            unsafe { drop(Box::from_raw(new)) };
+            unsafe { &*existing }
+        }
+    }
+}
+
+/// A connection that can also report write readiness. The same contract as
+/// `Pollable` applies to the write trigger.
+pub(crate) unsafe trait PollableTx: Pollable {
+    /// Like `poll_arm`, but `trigger` fires whenever the source can accept
+    /// a write without blocking.
+    unsafe fn poll_arm_tx(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()>;
+
+    /// Detaches a trigger previously attached with `poll_arm_tx`.
+    unsafe fn poll_disarm_tx(&self, trigger: *mut ffi::poll_trigger_t);
+}
+
+/// Read and write readiness for a connection, delivered through the reactor.
+/// Any number of tasks may wait on either direction. The owner must call
+/// `deregister` with the same source before either is dropped.
+pub(crate) struct IoSource {
+    token: usize,
+    rx_trigger: Box<ffi::poll_trigger_t>,
+    tx_trigger: Box<ffi::poll_trigger_t>,
+    readable: Arc<Signal>,
+    writable: Arc<Signal>,
+}
+impl IoSource {
+    pub(crate) fn register(source: &dyn PollableTx) -> io::Result<Self> {
+        let r = reactor();
+        // The read trigger uses `token`, the write trigger `token + 1`.
+        let token = r.next_token.fetch_add(2, Ordering::Relaxed);
+        let readable = Arc::new(Signal::new());
+        let writable = Arc::new(Signal::new());
+        let rx_trigger = poll::new_trigger();
+        let tx_trigger = poll::new_trigger();
+
+        // Arming may fire immediately, so the tokens must already resolve.
+        {
+            let mut sources = r.sources.lock();
+            sources.insert(token, readable.clone());
+            sources.insert(token + 1, writable.clone());
+        }
+        let rx_raw = &*rx_trigger as *const _ as *mut _;
+        let tx_raw = &*tx_trigger as *const _ as *mut _;
+        let res = unsafe {
+            source.poll_arm(r.poller.as_raw(), rx_raw, token).and_then(|()| {
+                let res = source.poll_arm_tx(r.poller.as_raw(), tx_raw, token + 1);
+                if res.is_err() {
+                    source.poll_disarm(rx_raw);
+                }
+                res
+            })
+        };
+        if let Err(e) = res {
+            let mut sources = r.sources.lock();
+            sources.remove(&token);
+            sources.remove(&(token + 1));
+            return Err(e);
+        }
+
+        Ok(IoSource {
+            token: token,
+            rx_trigger: rx_trigger,
+            tx_trigger: tx_trigger,
+            readable: readable,
+            writable: writable,
+        })
+    }
+
+    /// Ready once the source has data (or EOF) to read. Readiness is
+    /// consumed, and re-signalled by the runtime if data is left after the
+    /// following read. It may be stale, so callers must retry a read that
+    /// would block.
+    pub(crate) fn poll_readable(&self, cx: &mut Context) -> Poll<()> {
+        self.readable.poll_take(cx)
+    }
+
+    /// Ready once a write may proceed without blocking. Like read readiness,
+    /// it is consumed and may be stale.
+    pub(crate) fn poll_writable(&self, cx: &mut Context) -> Poll<()> {
+        self.writable.poll_take(cx)
+    }
+
+    pub(crate) unsafe fn deregister(&self, source: &dyn PollableTx) {
+        source.poll_disarm(&*self.rx_trigger as *const _ as *mut _);
+        source.poll_disarm_tx(&*self.tx_trigger as *const _ as *mut _);
+        let mut sources = reactor().sources.lock();
+        sources.remove(&self.token);
+        sources.remove(&(self.token + 1));
+    }
+}
+// The triggers are only read or written by the runtime, under the poller's
+// and the source's locks; this side only hands out their addresses.
+unsafe impl Send for IoSource {}
+unsafe impl Sync for IoSource {}
+
+/// Retries a non-blocking operation until it completes, or until it would
+/// block and `ready` has registered the task to be woken.
+pub(crate) fn poll_io<T, F, R>(mut op: F, mut ready: R) -> Poll<io::Result<T>>
+where
+    F: FnMut() -> io::Result<T>,
+    R: FnMut() -> Poll<()>,
+{
+    loop {
+        match op() {
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+            res => return Poll::Ready(res),
+        }
+        // A stale readiness event is consumed here; go around again.
+        if ready().is_pending() {
+            return Poll::Pending;
+        }
+    }
+}
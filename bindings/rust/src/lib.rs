@@ -8,9 +8,11 @@
 #![feature(get_mut_unchecked)]
 
 extern crate byteorder;
+extern crate libc;
 
 use std::cell::UnsafeCell;
-use std::ffi::CString;
+use std::cmp;
+use std::io;
 use std::mem;
 use std::os::raw::{c_int, c_void};
 use std::sync::atomic::{AtomicI32, Ordering};
@@ -21,19 +23,49 @@ pub mod ffi {
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
 
+pub mod alloc;
 mod asm;
+#[cfg(feature = "breakwater")]
+pub mod breakwater;
+mod channel;
+pub mod config;
+mod error;
+pub mod executor;
+pub mod percore;
+pub mod poll;
+pub mod runtime;
 pub mod storage;
+pub mod sync;
 pub mod tcp;
 pub mod thread;
+pub mod timer;
 pub mod udp;
 
 pub use asm::*;
+pub use error::{io_errno, Error};
+
+use error::io_error;
+pub use executor::{block_on, spawn_async};
+pub use thread::{set_panic_policy, PanicPolicy};
+
+/// Converts an optional socket timeout into the runtime's representation, where
+/// zero means block forever. Like std, a zero `Duration` is rejected.
+fn timeout_to_us(timeout: Option<Duration>) -> io::Result<u64> {
+    match timeout {
+        Some(d) if d == Duration::from_secs(0) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "cannot set a 0 duration timeout",
+        )),
+        Some(d) => Ok(cmp::max(timer::duration_to_us(d), 1)),
+        None => Ok(0),
+    }
+}
 
-fn convert_error(ret: c_int) -> Result<(), i32> {
-    if ret == 0 {
-        Ok(())
+fn us_to_timeout(us: u64) -> Option<Duration> {
+    if us == 0 {
+        None
     } else {
-        Err(ret as i32)
+        Some(Duration::from_micros(us))
     }
 }
 
@@ -57,13 +89,19 @@ pub fn preempt_disable() {
 }
 
 #[allow(unused)]
-pub fn base_init() -> Result<(), i32> {
-    convert_error(unsafe { ffi::base_init() })
+pub fn base_init() -> Result<(), Error> {
+    match unsafe { ffi::base_init() } {
+        0 => Ok(()),
+        ret => Err(Error::from_errno(ret)),
+    }
 }
 
 #[allow(unused)]
-pub fn base_init_thread() -> Result<(), i32> {
-    convert_error(unsafe { ffi::base_init_thread() })
+pub fn base_init_thread() -> Result<(), Error> {
+    match unsafe { ffi::base_init_thread() } {
+        0 => Ok(()),
+        ret => Err(Error::from_errno(ret)),
+    }
 }
 
 pub fn delay_us(microseconds: u64) {
@@ -81,18 +119,26 @@ pub fn sleep(duration: Duration) {
     }
 }
 
-pub fn runtime_init<F>(cfgpath: String, f: F) -> Result<(), i32>
+/// Starts the runtime and runs `f` as its main uthread. Only returns if
+/// startup fails: from then on the calling thread is one of the runtime's
+/// kthreads, and the process exits when `f` returns. A panic in `f` cannot
+/// be returned here, so it goes through the panic policy and the process then
+/// exits with a failure status.
+pub fn runtime_init<F>(cfgpath: String, f: F) -> Result<(), Error>
+where
+    F: FnOnce(),
+    F: Send + 'static,
+{
+    runtime::RuntimeBuilder::new().start(cfgpath, f)
+}
+
+/// Like `runtime_init`, but with a config built in code.
+pub fn runtime_init_with<F>(cfg: &config::RuntimeConfig, f: F) -> Result<(), Error>
 where
     F: FnOnce(),
     F: Send + 'static,
 {
-    convert_error(unsafe {
-        ffi::runtime_init(
-            CString::new(cfgpath).unwrap().into_raw(),
-            Some(thread::box_trampoline::<F>),
-            Box::into_raw(Box::new(f)) as *mut c_void,
-        )
-    })
+    runtime::RuntimeBuilder::new().start_with(cfg, f)
 }
 
 #[derive(Clone)]
@@ -0,0 +1,151 @@
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use super::*;
+
+/// Keeps each core's slot on its own cache line.
+#[repr(align(64))]
+struct CachePadded<T>(T);
+
+/// One `T` per runtime core, so that hot state can be updated without
+/// bouncing a shared cache line between cores.
+///
+/// Other cores may read a slot (through `iter`) while its owner updates it,
+/// so slots are shared rather than exclusive: mutate them through atomics or
+/// other `Sync` interior mutability.
+pub struct PerCore<T> {
+    slots: Box<[CachePadded<T>]>,
+}
+impl<T> PerCore<T> {
+    /// Creates one slot for each core the runtime may run on. Must be called
+    /// after the runtime has been initialized.
+    pub fn new<F: FnMut() -> T>(mut init: F) -> Self {
+        let slots: Vec<_> = (0..runtime::max_cores())
+            .map(|_| CachePadded(init()))
+            .collect();
+        PerCore {
+            slots: slots.into_boxed_slice(),
+        }
+    }
+
+    /// Returns the current core's slot. Preemption is disabled until the
+    /// guard is dropped, so the uthread cannot migrate away from the slot,
+    /// and must not block while holding it.
+    pub fn get(&self) -> PerCoreGuard<T> {
+        preempt_disable();
+        let core = thread::get_current_affinity() as usize;
+        PerCoreGuard {
+            value: &self.slots[core].0,
+            _not_send: PhantomData,
+        }
+    }
+
+    /// Iterates over every core's slot, e.g. to aggregate them.
+    pub fn iter(&self) -> Iter<T> {
+        Iter {
+            inner: self.slots.iter(),
+        }
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<T> {
+        IterMut {
+            inner: self.slots.iter_mut(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+unsafe impl<T: Send> Send for PerCore<T> {}
+unsafe impl<T: Send + Sync> Sync for PerCore<T> {}
+
+/// Access to the current core's slot of a `PerCore`, with preemption
+/// disabled. Re-enables preemption when dropped, so it must be dropped by
+/// the uthread (and on the kthread) that created it.
+pub struct PerCoreGuard<'a, T: 'a> {
+    value: &'a T,
+    _not_send: PhantomData<*const ()>,
+}
+impl<'a, T> Deref for PerCoreGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+impl<'a, T> Drop for PerCoreGuard<'a, T> {
+    fn drop(&mut self) {
+        preempt_enable();
+    }
+}
+
+pub struct Iter<'a, T: 'a> {
+    inner: slice::Iter<'a, CachePadded<T>>,
+}
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<&'a T> {
+        self.inner.next().map(|s| &s.0)
+    }
+}
+
+pub struct IterMut<'a, T: 'a> {
+    inner: slice::IterMut<'a, CachePadded<T>>,
+}
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+    fn next(&mut self) -> Option<&'a mut T> {
+        self.inner.next().map(|s| &mut s.0)
+    }
+}
+
+/// A counter sharded across cores. Increments touch only the current core's
+/// cache line; reads sum every core and so may miss concurrent increments.
+pub struct PerCoreCounter {
+    counts: PerCore<AtomicU64>,
+}
+impl PerCoreCounter {
+    pub fn new() -> Self {
+        PerCoreCounter {
+            counts: PerCore::new(|| AtomicU64::new(0)),
+        }
+    }
+
+    #[inline]
+    pub fn add(&self, n: u64) {
+        // Only the owning core writes its slot, and preemption is disabled,
+        // so a plain load and store is enough.
+        let count = self.counts.get();
+        count.store(count.load(Ordering::Relaxed).wrapping_add(n), Ordering::Relaxed);
+    }
+
+    #[inline]
+    pub fn inc(&self) {
+        self.add(1)
+    }
+
+    pub fn sum(&self) -> u64 {
+        self.counts
+            .iter()
+            .fold(0, |sum, c| sum.wrapping_add(c.load(Ordering::Relaxed)))
+    }
+
+    /// Resets the counter to zero. Increments racing with the reset may be
+    /// lost.
+    pub fn reset(&self) {
+        for c in self.counts.iter() {
+            c.store(0, Ordering::Relaxed);
+        }
+    }
+}
+impl Default for PerCoreCounter {
+    fn default() -> Self {
+        PerCoreCounter::new()
+    }
+}
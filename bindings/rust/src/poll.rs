@@ -0,0 +1,182 @@
+use std::cell::UnsafeCell;
+use std::io;
+use std::marker::PhantomData;
+use std::os::raw::c_ulong;
+use std::ptr;
+
+use super::*;
+
+/// A source of readiness events that can be registered with a `Poller`.
+///
+/// # Safety
+///
+/// `Poller` frees a trigger as soon as its registration is dropped, so an
+/// implementation must really link the trigger into the source in
+/// `poll_arm`, and unlink it in `poll_disarm` such that the source never
+/// touches it again.
+pub unsafe trait Pollable {
+    /// Attaches `trigger` to this source, so that `waiter` is woken with
+    /// `token` whenever the source becomes ready.
+    ///
+    /// # Safety
+    ///
+    /// `waiter` and `trigger` must stay valid and in place until the trigger
+    /// is passed to `poll_disarm`.
+    unsafe fn poll_arm(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()>;
+
+    /// Detaches a trigger previously attached with `poll_arm`.
+    ///
+    /// # Safety
+    ///
+    /// `trigger` must have been armed on this source and not yet disarmed.
+    unsafe fn poll_disarm(&self, trigger: *mut ffi::poll_trigger_t);
+}
+
+/// Allocates an unarmed trigger. Triggers are linked into their waiter's
+/// list while pending, so they must not move once armed.
+pub(crate) fn new_trigger() -> Box<ffi::poll_trigger_t> {
+    Box::new(ffi::poll_trigger_t {
+        link: ffi::list_node {
+            next: ptr::null_mut(),
+            prev: ptr::null_mut(),
+        },
+        waiter: ptr::null_mut(),
+        triggered: false,
+        data: 0,
+    })
+}
+
+/// Waits on many sources at once from a single uthread. Sources report
+/// read readiness: connections are ready when they have data queued or have
+/// been closed, and stay ready (re-firing after each read) until drained.
+pub struct Poller {
+    inner: Box<ffi::poll_waiter_t>,
+}
+impl Poller {
+    pub fn new() -> Self {
+        let mut inner = Box::new_uninit();
+        unsafe { ffi::poll_init(inner.as_mut_ptr()) };
+        Self {
+            inner: unsafe { inner.assume_init() },
+        }
+    }
+
+    #[inline]
+    pub(crate) fn as_raw(&self) -> *mut ffi::poll_waiter_t {
+        &*self.inner as *const _ as *mut _
+    }
+
+    /// Registers `source` with this poller. `wait` returns `token` each time
+    /// the source becomes ready, until the returned `Registration` is dropped.
+    pub fn register<'a>(
+        &'a self,
+        source: &'a dyn Pollable,
+        token: usize,
+    ) -> io::Result<Registration<'a>> {
+        let trigger = new_trigger();
+        let raw = &*trigger as *const _ as *mut _;
+        unsafe { source.poll_arm(self.as_raw(), raw, token)? };
+        Ok(Registration {
+            trigger: trigger,
+            source: source,
+            _poller: PhantomData,
+        })
+    }
+
+    /// Parks until a registered source is ready and returns its token.
+    pub fn wait(&self) -> usize {
+        unsafe { ffi::poll_wait(self.as_raw()) as usize }
+    }
+}
+impl Default for Poller {
+    fn default() -> Self {
+        Poller::new()
+    }
+}
+unsafe impl Send for Poller {}
+
+/// Keeps a source registered with a `Poller`. Dropping it unregisters the
+/// source and discards any event it had pending.
+pub struct Registration<'a> {
+    trigger: Box<ffi::poll_trigger_t>,
+    source: &'a dyn Pollable,
+    _poller: PhantomData<&'a Poller>,
+}
+impl<'a> Registration<'a> {
+    #[inline]
+    fn as_raw(&self) -> *mut ffi::poll_trigger_t {
+        &*self.trigger as *const _ as *mut _
+    }
+}
+impl<'a> Drop for Registration<'a> {
+    fn drop(&mut self) {
+        unsafe { self.source.poll_disarm(self.as_raw()) }
+    }
+}
+
+/// A user-controlled event source. Each call to `fire` makes a registered
+/// poller return the trigger's token once; firing while unregistered, or
+/// while an earlier event is still pending, has no effect.
+pub struct Trigger {
+    lock: SpinLock,
+    armed: UnsafeCell<*mut ffi::poll_trigger_t>,
+}
+impl Trigger {
+    pub fn new() -> Self {
+        Trigger {
+            lock: SpinLock::new(),
+            armed: UnsafeCell::new(ptr::null_mut()),
+        }
+    }
+
+    pub fn fire(&self) {
+        self.lock.lock_np();
+        unsafe {
+            let t = *self.armed.get();
+            if !t.is_null() {
+                ffi::poll_trigger((*t).waiter, t);
+            }
+        }
+        self.lock.unlock_np();
+    }
+}
+impl Default for Trigger {
+    fn default() -> Self {
+        Trigger::new()
+    }
+}
+unsafe impl Pollable for Trigger {
+    unsafe fn poll_arm(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()> {
+        self.lock.lock_np();
+        let res = if (*self.armed.get()).is_null() {
+            ffi::poll_arm(waiter, trigger, token as c_ulong);
+            *self.armed.get() = trigger;
+            Ok(())
+        } else {
+            Err(io_error(-libc::EBUSY))
+        };
+        self.lock.unlock_np();
+        res
+    }
+
+    unsafe fn poll_disarm(&self, trigger: *mut ffi::poll_trigger_t) {
+        self.lock.lock_np();
+        let armed = *self.armed.get();
+        *self.armed.get() = ptr::null_mut();
+        self.lock.unlock_np();
+        assert_eq!(armed, trigger);
+        ffi::poll_disarm(trigger);
+    }
+}
+unsafe impl Send for Trigger {}
+unsafe impl Sync for Trigger {}
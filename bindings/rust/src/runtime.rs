@@ -0,0 +1,255 @@
+use std::ffi::CString;
+use std::io;
+use std::os::raw::{c_int, c_void};
+use std::panic;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, Ordering};
+use std::time::Duration;
+
+use super::*;
+
+/// Packet queueing delay plus runtime queueing delay. Always zero while the
+/// runtime has idle cores it could still be granted.
+pub fn queue_delay() -> Duration {
+    unsafe {
+        if active_cores() < max_cores() || ffi::runtime_congestion.is_null() {
+            return Duration::from_secs(0);
+        }
+        Duration::from_micros(ptr::read_volatile(&(*ffi::runtime_congestion).delay_us))
+    }
+}
+
+/// Current CPU usage, in cores.
+pub fn load() -> f32 {
+    unsafe {
+        if ffi::runtime_congestion.is_null() {
+            return 0.0;
+        }
+        ptr::read_volatile(&(*ffi::runtime_congestion).load)
+    }
+}
+
+pub fn active_cores() -> u32 {
+    unsafe { ptr::read_volatile(ptr::addr_of!(ffi::runningks.cnt)) as u32 }
+}
+
+/// The most cores the IOKernel could grant this runtime.
+pub fn max_cores() -> u32 {
+    unsafe { ffi::maxks }
+}
+
+/// The cores the IOKernel will always grant this runtime if it needs them.
+pub fn guaranteed_cores() -> u32 {
+    unsafe { ffi::guaranteedks }
+}
+
+/// A snapshot of the congestion signals published by the IOKernel.
+#[derive(Debug, Copy, Clone)]
+pub struct RuntimeLoad {
+    pub queue_delay: Duration,
+    pub load: f32,
+    pub active_cores: u32,
+    pub max_cores: u32,
+    pub guaranteed_cores: u32,
+}
+impl RuntimeLoad {
+    pub fn current() -> Self {
+        RuntimeLoad {
+            queue_delay: queue_delay(),
+            load: load(),
+            active_cores: active_cores(),
+            max_cores: max_cores(),
+            guaranteed_cores: guaranteed_cores(),
+        }
+    }
+}
+
+/// The startup stage at which `RuntimeBuilder::start` failed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum InitStage {
+    /// The runtime's own initialization (config, subsystems, IOKernel).
+    Runtime,
+    Global,
+    Kthread,
+    Late,
+}
+
+type OnceHook = Box<dyn FnOnce() -> io::Result<()> + Send + 'static>;
+type KthreadHook = Box<dyn Fn() -> io::Result<()> + Send + Sync + 'static>;
+
+// Hooks run on kthreads before the scheduler starts (kthread hooks
+// concurrently, on their own pthreads), so these are std, not runtime, mutexes.
+struct Hooks {
+    global: std::sync::Mutex<Option<OnceHook>>,
+    kthread: Option<KthreadHook>,
+    late: std::sync::Mutex<Option<OnceHook>>,
+    failed: std::sync::Mutex<Option<Error>>,
+}
+
+static HOOKS: AtomicPtr<Hooks> = AtomicPtr::new(0 as *mut Hooks);
+
+fn hooks() -> &'static Hooks {
+    unsafe { &*HOOKS.load(Ordering::Acquire) }
+}
+
+fn run_hook<F: FnOnce() -> io::Result<()>>(stage: InitStage, f: F) -> c_int {
+    let error = match panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return 0,
+        Ok(Err(e)) => e,
+        Err(_) => io::Error::new(io::ErrorKind::Other, "initializer panicked"),
+    };
+    let ret = -io_errno(&error).unwrap_or(libc::EINVAL);
+
+    let mut failed = hooks().failed.lock().unwrap();
+    if failed.is_none() {
+        *failed = Some(Error::Init(stage, error));
+    }
+    ret
+}
+
+extern "C" fn global_hook() -> c_int {
+    let f = hooks().global.lock().unwrap().take();
+    match f {
+        Some(f) => run_hook(InitStage::Global, f),
+        None => 0,
+    }
+}
+
+extern "C" fn kthread_hook() -> c_int {
+    match hooks().kthread {
+        Some(ref f) => run_hook(InitStage::Kthread, f),
+        None => 0,
+    }
+}
+
+extern "C" fn late_hook() -> c_int {
+    let f = hooks().late.lock().unwrap().take();
+    match f {
+        Some(f) => run_hook(InitStage::Late, f),
+        None => 0,
+    }
+}
+
+/// Starts the runtime with user hooks that run during initialization: once
+/// globally before any kthread starts, once on every kthread, and once late,
+/// after the runtime is up but before the main uthread is spawned. A hook
+/// that fails stops startup before the main uthread can run.
+///
+/// ```ignore
+/// RuntimeBuilder::new()
+///     .kthread_init(|| init_percore_cache())
+///     .start(cfgpath, main)?;
+/// ```
+pub struct RuntimeBuilder {
+    global: Option<OnceHook>,
+    kthread: Option<KthreadHook>,
+    late: Option<OnceHook>,
+}
+impl RuntimeBuilder {
+    pub fn new() -> Self {
+        RuntimeBuilder {
+            global: None,
+            kthread: None,
+            late: None,
+        }
+    }
+
+    pub fn global_init<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce() -> io::Result<()>,
+        F: Send + 'static,
+    {
+        self.global = Some(Box::new(f));
+        self
+    }
+
+    /// Runs on each kthread after its runtime state is set up. Unlike the
+    /// other hooks, it runs on plain pthreads and must not block in the
+    /// runtime.
+    pub fn kthread_init<F>(mut self, f: F) -> Self
+    where
+        F: Fn() -> io::Result<()>,
+        F: Send + Sync + 'static,
+    {
+        self.kthread = Some(Box::new(f));
+        self
+    }
+
+    /// Runs once on the calling thread after every kthread is set up and the
+    /// runtime has registered with the IOKernel, before the main uthread is
+    /// spawned. Like `kthread_init`, it must not block in the runtime.
+    pub fn late_init<F>(mut self, f: F) -> Self
+    where
+        F: FnOnce() -> io::Result<()>,
+        F: Send + 'static,
+    {
+        self.late = Some(Box::new(f));
+        self
+    }
+
+    /// Starts the runtime and runs `f` as its first uthread. Like
+    /// `runtime_init`, only returns if startup fails; the process exits once
+    /// `f` returns, with a failure status if it panicked.
+    pub fn start<F>(self, cfgpath: String, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        let new = Box::into_raw(Box::new(Hooks {
+            global: std::sync::Mutex::new(self.global),
+            kthread: self.kthread,
+            late: std::sync::Mutex::new(self.late),
+            failed: std::sync::Mutex::new(None),
+        }));
+        let prev = HOOKS.swap(new, Ordering::AcqRel);
+        assert!(prev.is_null(), "runtime already started");
+
+        let main = move || {
+            if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(f)) {
+                thread::report_panic(payload);
+                unsafe { ffi::init_shutdown(libc::EXIT_FAILURE) }
+            }
+        };
+        let ret = unsafe { init(CString::new(cfgpath).unwrap(), main) };
+
+        // `runtime_init` only returns if startup failed.
+        match hooks().failed.lock().unwrap().take() {
+            Some(e) => Err(e),
+            None if ret != 0 => Err(Error::Init(InitStage::Runtime, io_error(ret))),
+            None => Ok(()),
+        }
+    }
+
+    /// Like `start`, but with a config built in code rather than read from a
+    /// file. The config is validated again before the runtime sees it.
+    pub fn start_with<F>(self, cfg: &config::RuntimeConfig, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        let file = cfg
+            .to_temp_file()
+            .map_err(|e| Error::Init(InitStage::Runtime, e))?;
+        let cfgpath = format!("/proc/self/fd/{}", file.as_raw_fd());
+        self.start(cfgpath, f)
+    }
+}
+
+unsafe fn init<F>(cfgpath: CString, f: F) -> c_int
+where
+    F: FnOnce(),
+    F: Send + 'static,
+{
+    ffi::runtime_set_initializers(Some(global_hook), Some(kthread_hook), Some(late_hook));
+    ffi::runtime_init(
+        cfgpath.into_raw(),
+        Some(thread::box_trampoline::<F>),
+        Box::into_raw(Box::new(f)) as *mut c_void,
+    )
+}
+impl Default for RuntimeBuilder {
+    fn default() -> Self {
+        RuntimeBuilder::new()
+    }
+}
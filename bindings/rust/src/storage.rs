@@ -1,4 +1,9 @@
-use std::io::{Error, ErrorKind, Result};
+use std::cmp;
+use std::future::Future;
+use std::io::{Error, ErrorKind, Read, Result, Seek, SeekFrom, Write};
+use std::pin::Pin;
+use std::sync::atomic::AtomicU64;
+use std::task::{Context, Poll};
 
 use super::*;
 
@@ -8,6 +13,9 @@ extern "C" {
 
     #[link_name = "num_blocks"]
     static num_blocks: ffi::u_int64_t;
+
+    #[link_name = "max_xfer_blocks"]
+    static max_xfer_blocks: ffi::u_int32_t;
 }
 
 pub fn storage_block_size() -> Result<usize> {
@@ -26,12 +34,21 @@ pub fn storage_num_blocks() -> Result<usize> {
     return Ok(nblocks as usize);
 }
 
+/// The most blocks a single `StorageQueue` request may transfer.
+pub fn storage_max_xfer_blocks() -> Result<usize> {
+    let nblocks = unsafe { max_xfer_blocks };
+    if nblocks == 0 {
+        return Err(Error::new(ErrorKind::Other, "storage not enabled"));
+    }
+    return Ok(nblocks as usize);
+}
+
 pub fn storage_read(buf: &mut [u8], lba: u64) -> Result<usize> {
     let bsize = storage_block_size()?;
     let nblocks = buf.len() / bsize;
     let res = unsafe { ffi::storage_read(buf.as_mut_ptr() as *mut c_void, lba, nblocks as u32) };
     if res < 0 {
-        Err(Error::from_raw_os_error(res))
+        Err(io_error(res))
     } else {
         Ok((nblocks * bsize) as usize)
     }
@@ -42,8 +59,525 @@ pub fn storage_write(buf: &[u8], lba: u64) -> Result<usize> {
     let nblocks = buf.len() / bsize;
     let res = unsafe { ffi::storage_write(buf.as_ptr() as *const c_void, lba, nblocks as u32) };
     if res < 0 {
-        Err(Error::from_raw_os_error(res))
+        Err(io_error(res))
     } else {
         Ok((nblocks * bsize) as usize)
     }
 }
+
+/// The most blocks moved by a single storage request, to keep DMA buffer
+/// allocations bounded.
+const MAX_IO_BLOCKS: usize = 64;
+
+fn check(res: c_int) -> Result<()> {
+    if res < 0 {
+        Err(io_error(res))
+    } else {
+        Ok(())
+    }
+}
+
+/// Whole-block access to a device, beneath the byte addressing done by
+/// `BlockDevice`.
+trait BlockIo {
+    fn block_size(&self) -> usize;
+    fn len(&self) -> u64;
+    fn read_blocks(&self, buf: &mut [u8], lba: u64) -> Result<()>;
+    fn write_blocks(&self, buf: &[u8], lba: u64) -> Result<()>;
+}
+
+/// Clips a transfer of `len` bytes at `offset` to the end of the device.
+fn clip<D: BlockIo>(dev: &D, len: usize, offset: u64) -> usize {
+    cmp::min(len as u64, dev.len().saturating_sub(offset)) as usize
+}
+
+fn read_at<D: BlockIo>(dev: &D, buf: &mut [u8], offset: u64) -> Result<usize> {
+    let len = clip(dev, buf.len(), offset);
+    let bsize = dev.block_size();
+    let mut bounce = Vec::new();
+    let mut done = 0;
+    while done < len {
+        let pos = offset + done as u64;
+        let lba = pos / bsize as u64;
+        let off = (pos % bsize as u64) as usize;
+        let res = if off == 0 && len - done >= bsize {
+            let n = cmp::min((len - done) / bsize, MAX_IO_BLOCKS) * bsize;
+            dev.read_blocks(&mut buf[done..done + n], lba).map(|_| n)
+        } else {
+            bounce.resize(bsize, 0);
+            let n = cmp::min(bsize - off, len - done);
+            dev.read_blocks(&mut bounce, lba).map(|_| {
+                buf[done..done + n].copy_from_slice(&bounce[off..off + n]);
+                n
+            })
+        };
+        match res {
+            Ok(n) => done += n,
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(done)
+}
+
+fn write_at<D: BlockIo>(dev: &D, buf: &[u8], offset: u64) -> Result<usize> {
+    let len = clip(dev, buf.len(), offset);
+    let bsize = dev.block_size();
+    let mut bounce = Vec::new();
+    let mut done = 0;
+    while done < len {
+        let pos = offset + done as u64;
+        let lba = pos / bsize as u64;
+        let off = (pos % bsize as u64) as usize;
+        let res = if off == 0 && len - done >= bsize {
+            let n = cmp::min((len - done) / bsize, MAX_IO_BLOCKS) * bsize;
+            dev.write_blocks(&buf[done..done + n], lba).map(|_| n)
+        } else {
+            bounce.resize(bsize, 0);
+            let n = cmp::min(bsize - off, len - done);
+            dev.read_blocks(&mut bounce, lba).and_then(|_| {
+                bounce[off..off + n].copy_from_slice(&buf[done..done + n]);
+                dev.write_blocks(&bounce, lba).map(|_| n)
+            })
+        };
+        match res {
+            Ok(n) => done += n,
+            Err(e) if done == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(done)
+}
+
+/// Resolves `pos` against the cursor `cur` on a device of `len` bytes.
+fn seek_pos(len: u64, cur: u64, pos: SeekFrom) -> Result<u64> {
+    let (base, delta) = match pos {
+        SeekFrom::Start(n) => return Ok(n),
+        SeekFrom::End(n) => (len, n),
+        SeekFrom::Current(n) => (cur, n),
+    };
+    let new = if delta >= 0 {
+        base.checked_add(delta as u64)
+    } else {
+        base.checked_sub(delta.wrapping_neg() as u64)
+    };
+    new.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+/// A byte-addressed handle to the NVMe device, with a cursor for `Read`,
+/// `Write` and `Seek`.
+///
+/// Transfers are clipped to the end of the device. Ranges that do not start
+/// or end on a block boundary are read through a bounce buffer, and written
+/// by read-modify-write of the partial blocks; concurrent writes to the same
+/// block through different handles may therefore lose updates.
+#[derive(Debug, Clone)]
+pub struct BlockDevice {
+    block_size: usize,
+    num_blocks: u64,
+    pos: u64,
+}
+impl BlockDevice {
+    /// Fails if storage is not enabled in the runtime config.
+    pub fn open() -> Result<Self> {
+        Ok(BlockDevice {
+            block_size: storage_block_size()?,
+            num_blocks: storage_num_blocks()? as u64,
+            pos: 0,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// The capacity of the device in bytes.
+    pub fn len(&self) -> u64 {
+        self.num_blocks * self.block_size as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_blocks == 0
+    }
+
+    /// Reads from byte `offset`, independent of the cursor. Returns 0 at or
+    /// past the end of the device.
+    pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        read_at(self, buf, offset)
+    }
+
+    /// Writes at byte `offset`, independent of the cursor. Returns 0 at or
+    /// past the end of the device.
+    pub fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        write_at(self, buf, offset)
+    }
+}
+impl BlockIo for BlockDevice {
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn len(&self) -> u64 {
+        BlockDevice::len(self)
+    }
+
+    fn read_blocks(&self, buf: &mut [u8], lba: u64) -> Result<()> {
+        let nblocks = buf.len() / self.block_size;
+        check(unsafe { ffi::storage_read(buf.as_mut_ptr() as *mut c_void, lba, nblocks as u32) })
+    }
+
+    fn write_blocks(&self, buf: &[u8], lba: u64) -> Result<()> {
+        let nblocks = buf.len() / self.block_size;
+        check(unsafe { ffi::storage_write(buf.as_ptr() as *const c_void, lba, nblocks as u32) })
+    }
+}
+impl Read for BlockDevice {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+impl Write for BlockDevice {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let n = self.write_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    /// Writes complete before `write` returns, so there is nothing to flush.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+impl Seek for BlockDevice {
+    /// Seeking past the end of the device is allowed; reads and writes there
+    /// transfer nothing.
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.pos = seek_pos(self.len(), self.pos, pos)?;
+        Ok(self.pos)
+    }
+}
+
+struct StorageRequest {
+    done: executor::Signal,
+    status: AtomicI32,
+    submitted_us: u64,
+    completed_us: AtomicU64,
+    buf: UnsafeCell<Vec<u8>>,
+}
+unsafe impl Send for StorageRequest {}
+unsafe impl Sync for StorageRequest {}
+
+extern "C" fn storage_complete(arg: *mut c_void, status: c_int) {
+    // Runs in the storage softirq, so it only records the result and wakes
+    // the waiter.
+    let req = unsafe { Arc::from_raw(arg as *const StorageRequest) };
+    req.status.store(status, Ordering::Relaxed);
+    req.completed_us.store(microtime(), Ordering::Relaxed);
+    req.done.notify();
+}
+
+/// Submits block requests to the NVMe device without blocking, so that a
+/// single uthread can keep many of them in flight.
+///
+/// Each submission returns a `Completion`, which can be awaited, waited on
+/// directly, or waited on together with others through `wait_any` and
+/// `wait_all`.
+#[derive(Debug, Clone)]
+pub struct StorageQueue {
+    block_size: usize,
+    num_blocks: u64,
+    max_blocks: u64,
+}
+impl StorageQueue {
+    /// Fails if storage is not enabled in the runtime config.
+    pub fn new() -> Result<Self> {
+        Ok(StorageQueue {
+            block_size: storage_block_size()?,
+            num_blocks: storage_num_blocks()? as u64,
+            max_blocks: storage_max_xfer_blocks()? as u64,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    pub fn num_blocks(&self) -> u64 {
+        self.num_blocks
+    }
+
+    /// The most blocks a single request may transfer.
+    pub fn max_blocks(&self) -> u64 {
+        self.max_blocks
+    }
+
+    fn check_range(&self, lba: u64, nblocks: u64) -> Result<()> {
+        if nblocks == 0 || nblocks > self.max_blocks {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "block count must be between 1 and the device's max transfer size",
+            ));
+        }
+        match lba.checked_add(nblocks) {
+            Some(end) if end <= self.num_blocks => Ok(()),
+            _ => Err(Error::new(
+                ErrorKind::InvalidInput,
+                "block range extends past the end of the device",
+            )),
+        }
+    }
+
+    fn submit<F>(&self, buf: Vec<u8>, f: F) -> Result<Completion>
+    where
+        F: FnOnce(*mut u8, ffi::storage_cb_fn_t, *mut c_void) -> c_int,
+    {
+        let req = Arc::new(StorageRequest {
+            done: executor::Signal::new(),
+            status: AtomicI32::new(0),
+            submitted_us: microtime(),
+            completed_us: AtomicU64::new(0),
+            buf: UnsafeCell::new(buf),
+        });
+
+        // The device holds a reference until the completion callback runs.
+        let ptr = unsafe { (*req.buf.get()).as_mut_ptr() };
+        let arg = Arc::into_raw(req.clone()) as *mut c_void;
+        let res = f(ptr, Some(storage_complete), arg);
+        if res < 0 {
+            drop(unsafe { Arc::from_raw(arg as *const StorageRequest) });
+            return Err(io_error(res));
+        }
+        Ok(Completion { req: req })
+    }
+
+    /// Reads `nblocks` blocks starting at `lba`. The data is returned by the
+    /// completion.
+    pub fn read(&self, lba: u64, nblocks: u32) -> Result<Completion> {
+        self.check_range(lba, nblocks as u64)?;
+        let buf = vec![0; nblocks as usize * self.block_size];
+        self.submit(buf, |ptr, cb, arg| unsafe {
+            ffi::storage_read_async(ptr as *mut c_void, lba, nblocks, cb, arg)
+        })
+    }
+
+    /// Writes `buf`, which must be a whole number of blocks, starting at
+    /// `lba`. `buf` is copied before this returns.
+    pub fn write(&self, buf: &[u8], lba: u64) -> Result<Completion> {
+        if buf.len() % self.block_size != 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "write length is not a multiple of the block size",
+            ));
+        }
+        let nblocks = (buf.len() / self.block_size) as u64;
+        self.check_range(lba, nblocks)?;
+        self.submit(Vec::new(), |_, cb, arg| unsafe {
+            ffi::storage_write_async(buf.as_ptr() as *const c_void, lba, nblocks as u32, cb, arg)
+        })
+    }
+}
+
+/// The outcome of a successful storage request.
+#[derive(Debug)]
+pub struct Completed {
+    /// The blocks read; empty for writes.
+    pub data: Vec<u8>,
+    /// Time from submission until the device reported completion.
+    pub latency: Duration,
+}
+
+/// Handle to a request submitted through a `StorageQueue`. Resolves once the
+/// device completes the request. Dropping the handle does not cancel the
+/// request.
+pub struct Completion {
+    req: Arc<StorageRequest>,
+}
+impl Completion {
+    /// Parks the calling uthread until the request completes.
+    pub fn wait(self) -> Result<Completed> {
+        block_on(self)
+    }
+}
+impl Future for Completion {
+    type Output = Result<Completed>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.req.done.poll_take(cx) {
+            Poll::Ready(()) => {
+                let req = &self.req;
+                let status = req.status.load(Ordering::Relaxed);
+                if status < 0 {
+                    return Poll::Ready(Err(io_error(status)));
+                }
+                let latency = req.completed_us.load(Ordering::Relaxed) - req.submitted_us;
+                Poll::Ready(Ok(Completed {
+                    data: mem::replace(unsafe { &mut *req.buf.get() }, Vec::new()),
+                    latency: Duration::from_micros(latency),
+                }))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Parks until any of `completions` finishes, removes it, and returns its
+/// former index along with its result. Returns `None` if `completions` is
+/// empty.
+pub fn wait_any(completions: &mut Vec<Completion>) -> Option<(usize, Result<Completed>)> {
+    if completions.is_empty() {
+        return None;
+    }
+    let (i, res) = block_on(WaitAny {
+        completions: completions,
+    });
+    completions.remove(i);
+    Some((i, res))
+}
+
+struct WaitAny<'a> {
+    completions: &'a mut Vec<Completion>,
+}
+impl<'a> Future for WaitAny<'a> {
+    type Output = (usize, Result<Completed>);
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        for (i, c) in self.completions.iter_mut().enumerate() {
+            if let Poll::Ready(res) = Pin::new(c).poll(cx) {
+                return Poll::Ready((i, res));
+            }
+        }
+        Poll::Pending
+    }
+}
+
+/// Parks until every one of `completions` finishes, and returns their results
+/// in order.
+pub fn wait_all(completions: Vec<Completion>) -> Vec<Result<Completed>> {
+    completions.into_iter().map(Completion::wait).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// An in-memory device that records every block transfer.
+    struct MemDevice {
+        block_size: usize,
+        data: RefCell<Vec<u8>>,
+        ops: RefCell<Vec<(char, u64, usize)>>,
+    }
+    impl MemDevice {
+        fn new(bsize: usize, nblocks: usize) -> Self {
+            MemDevice {
+                block_size: bsize,
+                data: RefCell::new((0..bsize * nblocks).map(|i| i as u8).collect()),
+                ops: RefCell::new(Vec::new()),
+            }
+        }
+    }
+    impl BlockIo for MemDevice {
+        fn block_size(&self) -> usize {
+            self.block_size
+        }
+
+        fn len(&self) -> u64 {
+            self.data.borrow().len() as u64
+        }
+
+        fn read_blocks(&self, buf: &mut [u8], lba: u64) -> Result<()> {
+            assert_eq!(buf.len() % self.block_size, 0);
+            let start = lba as usize * self.block_size;
+            buf.copy_from_slice(&self.data.borrow()[start..start + buf.len()]);
+            self.ops.borrow_mut().push(('r', lba, buf.len() / self.block_size));
+            Ok(())
+        }
+
+        fn write_blocks(&self, buf: &[u8], lba: u64) -> Result<()> {
+            assert_eq!(buf.len() % self.block_size, 0);
+            let start = lba as usize * self.block_size;
+            self.data.borrow_mut()[start..start + buf.len()].copy_from_slice(buf);
+            self.ops.borrow_mut().push(('w', lba, buf.len() / self.block_size));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unaligned_write_preserves_neighbours() {
+        let dev = MemDevice::new(16, 4);
+        let before = dev.data.borrow().clone();
+        assert_eq!(write_at(&dev, &[0xff; 20], 10).unwrap(), 20);
+
+        let after = dev.data.borrow();
+        assert_eq!(&after[..10], &before[..10]);
+        assert!(after[10..30].iter().all(|&b| b == 0xff));
+        assert_eq!(&after[30..], &before[30..]);
+        // both partial blocks are read back before being written
+        assert_eq!(
+            *dev.ops.borrow(),
+            vec![('r', 0, 1), ('w', 0, 1), ('r', 1, 1), ('w', 1, 1)]
+        );
+    }
+
+    #[test]
+    fn aligned_transfers_skip_bounce() {
+        let dev = MemDevice::new(16, 4);
+        let mut buf = [0u8; 32];
+        assert_eq!(read_at(&dev, &mut buf, 16).unwrap(), 32);
+        assert_eq!(buf[0], 16);
+        assert_eq!(write_at(&dev, &buf, 0).unwrap(), 32);
+        assert_eq!(*dev.ops.borrow(), vec![('r', 1, 2), ('w', 0, 2)]);
+    }
+
+    #[test]
+    fn clip_at_device_end() {
+        let dev = MemDevice::new(16, 4);
+        let mut buf = [0u8; 32];
+        assert_eq!(read_at(&dev, &mut buf, 50).unwrap(), 14);
+        assert_eq!(buf[..14], dev.data.borrow()[50..]);
+        assert_eq!(write_at(&dev, &[0; 32], 60).unwrap(), 4);
+        assert_eq!(read_at(&dev, &mut buf, 64).unwrap(), 0);
+        assert_eq!(write_at(&dev, &buf, 1000).unwrap(), 0);
+    }
+
+    #[test]
+    fn seek() {
+        assert_eq!(seek_pos(64, 10, SeekFrom::Start(100)).unwrap(), 100);
+        assert_eq!(seek_pos(64, 10, SeekFrom::End(-4)).unwrap(), 60);
+        assert_eq!(seek_pos(64, 10, SeekFrom::End(8)).unwrap(), 72);
+        assert_eq!(seek_pos(64, 10, SeekFrom::Current(-10)).unwrap(), 0);
+
+        let e = seek_pos(64, 10, SeekFrom::End(-65)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        let e = seek_pos(64, 10, SeekFrom::Current(-11)).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        assert!(seek_pos(64, u64::max_value(), SeekFrom::Current(1)).is_err());
+    }
+
+    #[test]
+    fn queue_check_range() {
+        let q = StorageQueue {
+            block_size: 4096,
+            num_blocks: 1 << 40,
+            max_blocks: 32,
+        };
+        assert!(q.check_range(0, 32).is_ok());
+        assert!(q.check_range((1 << 40) - 1, 1).is_ok());
+        for &(lba, nblocks) in &[(0, 0), (0, 33), (0, u32::max_value() as u64), (1 << 40, 1)] {
+            let e = q.check_range(lba, nblocks).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::InvalidInput);
+        }
+        assert!(q.check_range(u64::max_value(), 1).is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use super::*;
+
+pub use channel::{
+    channel, sync_channel, Iter, Receiver, Select, SelectHandle, Selectable, Sender, SyncReceiver,
+    SyncSender,
+};
+pub use std::sync::mpsc::{RecvError, RecvTimeoutError, SendError, TryRecvError, TrySendError};
+
+/// A mutual exclusion lock that parks the calling uthread instead of blocking
+/// the kthread. Backed by the runtime's `mutex_t`.
+pub struct Mutex<T: ?Sized> {
+    inner: Box<ffi::mutex_t>,
+    data: UnsafeCell<T>,
+}
+impl<T> Mutex<T> {
+    pub fn new(t: T) -> Self {
+        let mut inner = Box::new_uninit();
+        unsafe { ffi::mutex_init(inner.as_mut_ptr()) };
+        Self {
+            inner: unsafe { inner.assume_init() },
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+impl<T: ?Sized> Mutex<T> {
+    #[inline]
+    fn as_raw(&self) -> *mut ffi::mutex_t {
+        &*self.inner as *const _ as *mut _
+    }
+
+    #[inline]
+    fn held(&self) -> &AtomicI32 {
+        unsafe { &*(&self.inner.held.cnt as *const _ as *const AtomicI32) }
+    }
+
+    pub fn lock(&self) -> MutexGuard<T> {
+        if self
+            .held()
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            unsafe { ffi::__mutex_lock(self.as_raw()) };
+        }
+        MutexGuard { lock: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<T>> {
+        if self
+            .held()
+            .compare_exchange(0, 1, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+        {
+            Some(MutexGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn unlock(&self) {
+        if self
+            .held()
+            .compare_exchange(1, 0, Ordering::Release, Ordering::Relaxed)
+            .is_err()
+        {
+            unsafe { ffi::__mutex_unlock(self.as_raw()) };
+        }
+    }
+}
+impl<T: Default> Default for Mutex<T> {
+    fn default() -> Self {
+        Mutex::new(T::default())
+    }
+}
+impl<T: ?Sized + fmt::Debug> fmt::Debug for Mutex<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_lock() {
+            Some(guard) => f.debug_struct("Mutex").field("data", &&*guard).finish(),
+            None => f.write_str("Mutex { <locked> }"),
+        }
+    }
+}
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+/// RAII guard for a `Mutex`. The lock is released when the guard is dropped.
+pub struct MutexGuard<'a, T: ?Sized + 'a> {
+    lock: &'a Mutex<T>,
+}
+impl<'a, T: ?Sized> Deref for MutexGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+impl<'a, T: ?Sized> DerefMut for MutexGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+impl<'a, T: ?Sized> Drop for MutexGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+unsafe impl<'a, T: ?Sized + Sync> Sync for MutexGuard<'a, T> {}
+
+/// A condition variable backed by the runtime's `condvar_t`.
+pub struct Condvar {
+    inner: Box<ffi::condvar_t>,
+}
+impl Condvar {
+    pub fn new() -> Self {
+        let mut inner = Box::new_uninit();
+        unsafe { ffi::condvar_init(inner.as_mut_ptr()) };
+        Self {
+            inner: unsafe { inner.assume_init() },
+        }
+    }
+
+    #[inline]
+    fn as_raw(&self) -> *mut ffi::condvar_t {
+        &*self.inner as *const _ as *mut _
+    }
+
+    /// Atomically releases the mutex held by `guard` and parks until notified,
+    /// then reacquires the mutex before returning.
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        unsafe { ffi::condvar_wait(self.as_raw(), guard.lock.as_raw()) };
+        guard
+    }
+
+    pub fn wait_while<'a, T: ?Sized, F>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: F,
+    ) -> MutexGuard<'a, T>
+    where
+        F: FnMut(&mut T) -> bool,
+    {
+        while condition(&mut *guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        unsafe { ffi::condvar_signal(self.as_raw()) }
+    }
+
+    pub fn notify_all(&self) {
+        unsafe { ffi::condvar_broadcast(self.as_raw()) }
+    }
+}
+impl Default for Condvar {
+    fn default() -> Self {
+        Condvar::new()
+    }
+}
+unsafe impl Send for Condvar {}
+unsafe impl Sync for Condvar {}
+
+/// A reader-writer lock backed by the runtime's `rwmutex_t`. Blocked readers
+/// and writers park their uthread.
+pub struct RwLock<T: ?Sized> {
+    inner: Box<ffi::rwmutex_t>,
+    data: UnsafeCell<T>,
+}
+impl<T> RwLock<T> {
+    pub fn new(t: T) -> Self {
+        let mut inner = Box::new_uninit();
+        unsafe { ffi::rwmutex_init(inner.as_mut_ptr()) };
+        Self {
+            inner: unsafe { inner.assume_init() },
+            data: UnsafeCell::new(t),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+impl<T: ?Sized> RwLock<T> {
+    #[inline]
+    fn as_raw(&self) -> *mut ffi::rwmutex_t {
+        &*self.inner as *const _ as *mut _
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<T> {
+        unsafe { ffi::rwmutex_rdlock(self.as_raw()) };
+        RwLockReadGuard { lock: self }
+    }
+
+    pub fn try_read(&self) -> Option<RwLockReadGuard<T>> {
+        if unsafe { ffi::rwmutex_try_rdlock(self.as_raw()) } {
+            Some(RwLockReadGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<T> {
+        unsafe { ffi::rwmutex_wrlock(self.as_raw()) };
+        RwLockWriteGuard { lock: self }
+    }
+
+    pub fn try_write(&self) -> Option<RwLockWriteGuard<T>> {
+        if unsafe { ffi::rwmutex_try_wrlock(self.as_raw()) } {
+            Some(RwLockWriteGuard { lock: self })
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.data.get() }
+    }
+
+    fn unlock(&self) {
+        unsafe { ffi::rwmutex_unlock(self.as_raw()) }
+    }
+}
+impl<T: Default> Default for RwLock<T> {
+    fn default() -> Self {
+        RwLock::new(T::default())
+    }
+}
+impl<T: ?Sized + fmt::Debug> fmt::Debug for RwLock<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.try_read() {
+            Some(guard) => f.debug_struct("RwLock").field("data", &&*guard).finish(),
+            None => f.write_str("RwLock { <locked> }"),
+        }
+    }
+}
+unsafe impl<T: ?Sized + Send> Send for RwLock<T> {}
+unsafe impl<T: ?Sized + Send + Sync> Sync for RwLock<T> {}
+
+/// RAII guard for shared access to a `RwLock`.
+pub struct RwLockReadGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+impl<'a, T: ?Sized> Deref for RwLockReadGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+impl<'a, T: ?Sized> Drop for RwLockReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// RAII guard for exclusive access to a `RwLock`.
+pub struct RwLockWriteGuard<'a, T: ?Sized + 'a> {
+    lock: &'a RwLock<T>,
+}
+impl<'a, T: ?Sized> Deref for RwLockWriteGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+impl<'a, T: ?Sized> DerefMut for RwLockWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+impl<'a, T: ?Sized> Drop for RwLockWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.unlock();
+    }
+}
+
+/// A barrier backed by the runtime's `barrier_t`. Waiting uthreads are parked
+/// until `n` of them have arrived, after which the barrier can be reused.
+pub struct Barrier {
+    inner: Box<ffi::barrier_t>,
+}
+impl Barrier {
+    pub fn new(n: usize) -> Self {
+        let mut inner = Box::new_uninit();
+        unsafe { ffi::barrier_init(inner.as_mut_ptr(), n as c_int) };
+        Self {
+            inner: unsafe { inner.assume_init() },
+        }
+    }
+
+    #[inline]
+    fn as_raw(&self) -> *mut ffi::barrier_t {
+        &*self.inner as *const _ as *mut _
+    }
+
+    pub fn wait(&self) -> BarrierWaitResult {
+        BarrierWaitResult(unsafe { ffi::barrier_wait(self.as_raw()) })
+    }
+}
+unsafe impl Send for Barrier {}
+unsafe impl Sync for Barrier {}
+
+#[derive(Debug)]
+pub struct BarrierWaitResult(bool);
+impl BarrierWaitResult {
+    /// Returns true for exactly one uthread per generation: the one whose
+    /// arrival released the barrier.
+    pub fn is_leader(&self) -> bool {
+        self.0
+    }
+}
@@ -1,6 +1,11 @@
-use std::io::{self, Read, Write};
+use std::future::Future;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
 use std::net::SocketAddrV4;
+use std::os::raw::c_ulong;
+use std::pin::Pin;
 use std::ptr;
+use std::task::{Context, Poll};
+use std::time::Duration;
 
 use byteorder::{ByteOrder, NetworkEndian};
 
@@ -10,7 +15,7 @@ fn isize_to_result(i: isize) -> io::Result<usize> {
     if i >= 0 {
         Ok(i as usize)
     } else {
-        Err(io::Error::from_raw_os_error(i as i32))
+        Err(io_error(i as i32))
     }
 }
 
@@ -24,7 +29,7 @@ impl TcpQueue {
         let mut queue = ptr::null_mut();
         let ret = unsafe { ffi::tcp_listen(laddr, backlog, &mut queue as *mut _) };
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            Err(io_error(ret as i32))
         } else {
             Ok(TcpQueue(queue))
         }
@@ -33,7 +38,7 @@ impl TcpQueue {
         let mut conn = ptr::null_mut();
         let ret = unsafe { ffi::tcp_accept(self.0, &mut conn as *mut _) };
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            Err(io_error(ret as i32))
         } else {
             Ok(TcpConnection(conn))
         }
@@ -65,7 +70,41 @@ impl TcpConnection {
         let mut conn = ptr::null_mut();
         let ret = unsafe { ffi::tcp_dial(laddr, raddr, &mut conn as *mut _) };
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            Err(io_error(ret as i32))
+        } else {
+            Ok(TcpConnection(conn))
+        }
+    }
+
+    /// Dials `remote_addr` from an ephemeral port whose ingress traffic is
+    /// steered to kthread `core`.
+    pub fn dial_affinity(core: u32, remote_addr: SocketAddrV4) -> io::Result<Self> {
+        let raddr = ffi::netaddr {
+            ip: NetworkEndian::read_u32(&remote_addr.ip().octets()),
+            port: remote_addr.port(),
+        };
+
+        let mut conn = ptr::null_mut();
+        let ret = unsafe { ffi::tcp_dial_affinity(core, raddr, &mut conn as *mut _) };
+        if ret < 0 {
+            Err(io_error(ret as i32))
+        } else {
+            Ok(TcpConnection(conn))
+        }
+    }
+
+    /// Dials `remote_addr` so that the new connection is handled by the same
+    /// kthread as `existing`.
+    pub fn dial_like(existing: &TcpConnection, remote_addr: SocketAddrV4) -> io::Result<Self> {
+        let raddr = ffi::netaddr {
+            ip: NetworkEndian::read_u32(&remote_addr.ip().octets()),
+            port: remote_addr.port(),
+        };
+
+        let mut conn = ptr::null_mut();
+        let ret = unsafe { ffi::tcp_dial_conn_affinity(existing.0, raddr, &mut conn as *mut _) };
+        if ret < 0 {
+            Err(io_error(ret as i32))
         } else {
             Ok(TcpConnection(conn))
         }
@@ -81,18 +120,69 @@ impl TcpConnection {
         SocketAddrV4::new(remote_addr.ip.into(), remote_addr.port)
     }
 
+    /// Sets how long a read may block before failing with `TimedOut`. `None`
+    /// blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let us = timeout_to_us(timeout)?;
+        unsafe { ffi::tcp_set_rx_timeout(self.0, us) };
+        Ok(())
+    }
+
+    /// Sets how long a write may block before failing with `TimedOut`. `None`
+    /// blocks indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let us = timeout_to_us(timeout)?;
+        unsafe { ffi::tcp_set_tx_timeout(self.0, us) };
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(us_to_timeout(unsafe { ffi::tcp_get_rx_timeout(self.0) }))
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(us_to_timeout(unsafe { ffi::tcp_get_tx_timeout(self.0) }))
+    }
+
     pub fn shutdown(&self, how: c_int) -> io::Result<()> {
         let res = unsafe { ffi::tcp_shutdown(self.0, how) };
         if res == 0 {
             Ok(())
         } else {
-            Err(io::Error::from_raw_os_error(res as i32))
+            Err(io_error(res as i32))
         }
     }
 
     pub fn abort(&self) {
         unsafe { ffi::tcp_abort(self.0) };
     }
+
+    // IoSlice and IoSliceMut are guaranteed to be ABI compatible with iovec.
+    fn readv(&self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        let iovcnt = cmp::min(bufs.len(), c_int::max_value() as usize) as c_int;
+        isize_to_result(unsafe {
+            ffi::tcp_readv(self.0, bufs.as_ptr() as *const ffi::iovec, iovcnt)
+        })
+    }
+
+    fn writev(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let iovcnt = cmp::min(bufs.len(), c_int::max_value() as usize) as c_int;
+        isize_to_result(unsafe {
+            ffi::tcp_writev(self.0, bufs.as_ptr() as *const ffi::iovec, iovcnt)
+        })
+    }
+
+    fn read_nonblock(&self, buf: &mut [u8]) -> io::Result<usize> {
+        isize_to_result(unsafe {
+            ffi::tcp_read_nonblock(self.0, buf.as_mut_ptr() as *mut c_void, buf.len())
+        })
+    }
+
+    fn write_nonblock(&self, buf: &[u8]) -> io::Result<usize> {
+        isize_to_result(unsafe {
+            ffi::tcp_write_nonblock(self.0, buf.as_ptr() as *const c_void, buf.len())
+        })
+    }
 }
 
 impl<'a> Read for &'a TcpConnection {
@@ -101,6 +191,9 @@ impl<'a> Read for &'a TcpConnection {
             ffi::tcp_read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len())
         })
     }
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        self.readv(bufs)
+    }
 }
 impl Read for TcpConnection {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
@@ -108,11 +201,17 @@ impl Read for TcpConnection {
             ffi::tcp_read(self.0, buf.as_mut_ptr() as *mut c_void, buf.len())
         })
     }
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut]) -> io::Result<usize> {
+        self.readv(bufs)
+    }
 }
 impl<'a> Write for &'a TcpConnection {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         isize_to_result(unsafe { ffi::tcp_write(self.0, buf.as_ptr() as *const c_void, buf.len()) })
     }
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.writev(bufs)
+    }
     fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
@@ -121,10 +220,51 @@ impl Write for TcpConnection {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
         isize_to_result(unsafe { ffi::tcp_write(self.0, buf.as_ptr() as *const c_void, buf.len()) })
     }
+    fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+        self.writev(bufs)
+    }
     fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
+unsafe impl poll::Pollable for TcpConnection {
+    unsafe fn poll_arm(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()> {
+        let ret = ffi::tcp_poll_arm(self.0, waiter, trigger, token as c_ulong);
+        if ret < 0 {
+            Err(io_error(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn poll_disarm(&self, trigger: *mut ffi::poll_trigger_t) {
+        ffi::tcp_poll_disarm(self.0, trigger)
+    }
+}
+unsafe impl executor::PollableTx for TcpConnection {
+    unsafe fn poll_arm_tx(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()> {
+        let ret = ffi::tcp_poll_arm_tx(self.0, waiter, trigger, token as c_ulong);
+        if ret < 0 {
+            Err(io_error(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn poll_disarm_tx(&self, trigger: *mut ffi::poll_trigger_t) {
+        ffi::tcp_poll_disarm_tx(self.0, trigger)
+    }
+}
 impl Drop for TcpConnection {
     fn drop(&mut self) {
         unsafe { ffi::tcp_close(self.0) }
@@ -132,3 +272,65 @@ impl Drop for TcpConnection {
 }
 unsafe impl Send for TcpConnection {}
 unsafe impl Sync for TcpConnection {}
+
+/// A `TcpConnection` whose reads and writes are awaitable. An empty receive
+/// queue or a full send window leaves the future pending instead of parking
+/// the uthread running the task.
+pub struct AsyncTcpConnection {
+    conn: TcpConnection,
+    io: executor::IoSource,
+}
+impl AsyncTcpConnection {
+    pub fn new(conn: TcpConnection) -> io::Result<Self> {
+        let io = executor::IoSource::register(&conn)?;
+        Ok(AsyncTcpConnection { conn: conn, io: io })
+    }
+
+    pub fn dial(local_addr: SocketAddrV4, remote_addr: SocketAddrV4) -> io::Result<Self> {
+        Self::new(TcpConnection::dial(local_addr, remote_addr)?)
+    }
+
+    pub fn read<'a>(&'a self, buf: &'a mut [u8]) -> TcpReadFuture<'a> {
+        TcpReadFuture { conn: self, buf: buf }
+    }
+
+    pub fn write<'a>(&'a self, buf: &'a [u8]) -> TcpWriteFuture<'a> {
+        TcpWriteFuture { conn: self, buf: buf }
+    }
+
+    pub fn get_ref(&self) -> &TcpConnection {
+        &self.conn
+    }
+}
+impl Drop for AsyncTcpConnection {
+    fn drop(&mut self) {
+        unsafe { self.io.deregister(&self.conn) }
+    }
+}
+
+pub struct TcpReadFuture<'a> {
+    conn: &'a AsyncTcpConnection,
+    buf: &'a mut [u8],
+}
+impl<'a> Future for TcpReadFuture<'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let conn = this.conn;
+        executor::poll_io(|| conn.conn.read_nonblock(this.buf), || conn.io.poll_readable(cx))
+    }
+}
+
+pub struct TcpWriteFuture<'a> {
+    conn: &'a AsyncTcpConnection,
+    buf: &'a [u8],
+}
+impl<'a> Future for TcpWriteFuture<'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let conn = self.conn;
+        executor::poll_io(|| conn.conn.write_nonblock(self.buf), || conn.io.poll_writable(cx))
+    }
+}
@@ -0,0 +1,26 @@
+extern crate shenango;
+
+use std::io::Write;
+use std::time::Duration;
+
+use shenango::breakwater::{Algorithm, Server};
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 3, "usage: [alg] [cfg_file]");
+    let algorithm: Algorithm = args[1].parse().unwrap();
+
+    shenango::runtime_init(args[2].clone(), move || {
+        let server = Server::enable(algorithm, |req, resp| {
+            resp.write_all(req).unwrap();
+        })
+        .unwrap();
+        println!("{} echo server listening on port {}", algorithm, shenango::breakwater::PORT);
+
+        loop {
+            shenango::sleep(Duration::from_secs(1));
+            println!("{:?}", server.stats());
+        }
+    })
+    .unwrap();
+}
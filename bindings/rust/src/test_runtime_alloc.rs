@@ -0,0 +1,72 @@
+extern crate shenango;
+
+use shenango::alloc::SmallocAllocator;
+use std::collections::HashMap;
+
+#[global_allocator]
+static ALLOC: SmallocAllocator = SmallocAllocator;
+
+const NTHREADS: usize = 4;
+const N: usize = 10000;
+
+#[repr(align(64))]
+struct CacheLine([u8; 64]);
+
+fn test_alloc() {
+    let join_handles: Vec<_> = (0..NTHREADS)
+        .map(|t| {
+            shenango::thread::spawn(move || {
+                let mut map = HashMap::new();
+                for i in 0..N {
+                    map.insert(i, vec![t as u8; i % 512]);
+                }
+                for i in 0..N {
+                    assert!(map[&i].iter().all(|&b| b == t as u8));
+                }
+            })
+        })
+        .collect();
+
+    for j in join_handles {
+        j.join().unwrap();
+    }
+    println!("alloc: ok");
+}
+
+fn test_aligned() {
+    let lines: Vec<_> = (0..N).map(|_| Box::new(CacheLine([0; 64]))).collect();
+    for l in &lines {
+        assert_eq!(&**l as *const CacheLine as usize % 64, 0);
+        assert_eq!(l.0[63], 0);
+    }
+    println!("aligned: ok");
+}
+
+fn test_realloc() {
+    let mut v: Vec<u32> = Vec::new();
+    for i in 0..(1 << 20) {
+        v.push(i);
+    }
+    // large enough to spill over into the system allocator
+    assert!(v.iter().enumerate().all(|(i, &x)| i as u32 == x));
+    v.truncate(10);
+    v.shrink_to_fit();
+    assert_eq!(v, (0..10).collect::<Vec<_>>());
+
+    let z = vec![0u64; 1000];
+    assert!(z.iter().all(|&x| x == 0));
+    println!("realloc: ok");
+}
+
+fn main_handler() {
+    test_alloc();
+    test_aligned();
+    test_realloc();
+}
+
+fn main() {
+    // allocations before runtime_init fall back to the system allocator
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
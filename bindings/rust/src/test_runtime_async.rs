@@ -0,0 +1,154 @@
+extern crate shenango;
+
+use shenango::tcp::AsyncTcpConnection;
+use shenango::timer;
+use shenango::udp::AsyncUdpConnection;
+use std::future::Future;
+use std::mem;
+use std::net::SocketAddrV4;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Wake, Waker};
+use std::time::{Duration, Instant};
+
+const NTASKS: usize = 100;
+
+fn test_spawn_async() {
+    // each outer task awaits the JoinHandle of an inner one
+    let handles: Vec<_> = (0..NTASKS)
+        .map(|i| shenango::spawn_async(async_double(i)))
+        .collect();
+
+    let sum: usize = handles
+        .into_iter()
+        .map(|h| h.join().unwrap().unwrap())
+        .sum();
+    assert_eq!(sum, NTASKS * (NTASKS - 1));
+    println!("spawn_async: ok");
+}
+
+fn async_double(i: usize) -> shenango::executor::JoinHandle<usize> {
+    shenango::spawn_async(Ready(Some(i * 2)))
+}
+
+fn test_delay() {
+    let start = Instant::now();
+    shenango::block_on(timer::delay(Duration::from_millis(10)));
+    assert!(start.elapsed() >= Duration::from_millis(10));
+
+    // a delay that is dropped before firing must not wake anyone later
+    drop(timer::delay(Duration::from_millis(1)));
+    println!("delay: ok");
+}
+
+fn test_panic() {
+    let h = shenango::spawn_async(Panic);
+    assert!(h.join().is_err());
+    println!("panic: ok");
+}
+
+struct NoopWake;
+impl Wake for NoopWake {
+    fn wake(self: Arc<Self>) {}
+}
+
+/// `peer` must echo back whatever it receives, over both TCP and UDP.
+fn test_connections(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let waker = Waker::from(Arc::new(NoopWake));
+    let mut cx = Context::from_waker(&waker);
+    let mut buf = [0u8; 64];
+
+    let tcp = AsyncTcpConnection::dial(any, peer).unwrap();
+    {
+        // nothing has been echoed yet, so the read must stay pending
+        let mut read = tcp.read(&mut buf);
+        assert!(Pin::new(&mut read).poll(&mut cx).is_pending());
+        assert_eq!(shenango::block_on(tcp.write(b"tcp ping")).unwrap(), 8);
+        assert_eq!(shenango::block_on(read).unwrap(), 8);
+    }
+    assert_eq!(&buf[..8], b"tcp ping");
+
+    let udp = AsyncUdpConnection::dial(any, peer).unwrap();
+    {
+        let mut read = udp.read_from(&mut buf);
+        assert!(Pin::new(&mut read).poll(&mut cx).is_pending());
+        assert_eq!(shenango::block_on(udp.write_to(b"udp ping", peer)).unwrap(), 8);
+        assert_eq!(shenango::block_on(read).unwrap(), (8, peer));
+    }
+    assert_eq!(&buf[..8], b"udp ping");
+    println!("connections: ok");
+}
+
+/// Two tasks on their own uthreads wait to read from one connection, so both
+/// are parked on its read readiness when the echo arrives.
+fn test_spawn_connections(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let tcp = Arc::new(AsyncTcpConnection::dial(any, peer).unwrap());
+    let readers: Vec<_> = (0..2)
+        .map(|_| shenango::spawn_async(ReadExact(tcp.clone(), vec![0; 4], 0)))
+        .collect();
+    shenango::sleep(Duration::from_millis(1));
+    assert_eq!(shenango::block_on(tcp.write(b"pingpong")).unwrap(), 8);
+
+    let mut got: Vec<_> = readers.into_iter().map(|h| h.join().unwrap()).collect();
+    got.sort();
+    assert_eq!(got, vec![b"ping".to_vec(), b"pong".to_vec()]);
+    println!("spawn_async connections: ok");
+}
+
+/// Fills its buffer from an `AsyncTcpConnection` it shares with other tasks.
+struct ReadExact(Arc<AsyncTcpConnection>, Vec<u8>, usize);
+impl std::future::Future for ReadExact {
+    type Output = Vec<u8>;
+    fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context) -> std::task::Poll<Vec<u8>> {
+        let this = &mut *self;
+        while this.2 < this.1.len() {
+            let mut read = this.0.read(&mut this.1[this.2..]);
+            match Pin::new(&mut read).poll(cx) {
+                std::task::Poll::Ready(n) => match n.unwrap() {
+                    0 => panic!("connection closed"),
+                    n => this.2 += n,
+                },
+                std::task::Poll::Pending => return std::task::Poll::Pending,
+            }
+        }
+        std::task::Poll::Ready(mem::replace(&mut this.1, Vec::new()))
+    }
+}
+
+// This crate is built as edition 2015, so the test futures are written by hand.
+struct Ready<T>(Option<T>);
+impl<T: Unpin> std::future::Future for Ready<T> {
+    type Output = T;
+    fn poll(mut self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context) -> std::task::Poll<T> {
+        std::task::Poll::Ready(self.0.take().unwrap())
+    }
+}
+
+struct Panic;
+impl std::future::Future for Panic {
+    type Output = ();
+    fn poll(self: std::pin::Pin<&mut Self>, _cx: &mut std::task::Context) -> std::task::Poll<()> {
+        panic!("expected panic")
+    }
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "usage: cfg_file [echo_peer_ip:port]");
+    let peer: Option<SocketAddrV4> = args.get(2).map(|a| a.parse().unwrap());
+    shenango::runtime_init(args[1].clone(), move || {
+        test_spawn_async();
+        test_delay();
+        test_panic();
+        match peer {
+            Some(peer) => {
+                test_connections(peer);
+                test_spawn_connections(peer);
+            }
+            None => println!("connections: skipped, no echo peer given"),
+        }
+    })
+    .unwrap();
+}
@@ -0,0 +1,81 @@
+extern crate shenango;
+
+use shenango::thread::{self, Builder};
+use std::io;
+use std::sync::{Arc, Mutex};
+
+// Idle kthreads may steal queued uthreads, so ordering and placement are
+// only checked to hold on at least one of several attempts.
+const ATTEMPTS: usize = 10;
+
+fn test_name() {
+    let name = Builder::new()
+        .name("worker".to_owned())
+        .spawn(thread::current_name)
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(name.as_ref().map(|s| s.as_str()), Some("worker"));
+
+    let name = Builder::new()
+        .spawn(thread::current_name)
+        .unwrap()
+        .join()
+        .unwrap();
+    assert_eq!(name, None);
+    println!("name: ok");
+}
+
+fn run_next_first() -> bool {
+    let order = Arc::new(Mutex::new(Vec::new()));
+    let spawn = |tag, run_next| {
+        let order = order.clone();
+        Builder::new()
+            .run_next(run_next)
+            .spawn(move || order.lock().unwrap().push(tag))
+            .unwrap()
+    };
+    let normal = spawn("normal", false);
+    let next = spawn("next", true);
+    normal.join().unwrap();
+    next.join().unwrap();
+    let order = order.lock().unwrap();
+    *order == ["next", "normal"]
+}
+
+fn test_run_next() {
+    assert!((0..ATTEMPTS).any(|_| run_next_first()));
+    println!("run_next: ok");
+}
+
+fn test_kthread() {
+    let nks = shenango::runtime::max_cores();
+    let target = nks - 1;
+    assert!((0..ATTEMPTS).any(|_| {
+        Builder::new()
+            .kthread(target)
+            .spawn(thread::get_current_affinity)
+            .unwrap()
+            .join()
+            .unwrap()
+            == target
+    }));
+
+    match Builder::new().kthread(nks).spawn(|| ()) {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("spawned on kthread {} of {}", nks, nks),
+    }
+    println!("kthread: ok");
+}
+
+fn main_handler() {
+    test_name();
+    test_run_next();
+    test_kthread();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
@@ -0,0 +1,109 @@
+extern crate shenango;
+
+use std::sync::mpsc::{RecvTimeoutError, TryRecvError, TrySendError};
+use std::time::{Duration, Instant};
+
+use shenango::sync::{channel, sync_channel, Select};
+
+fn test_mpsc() {
+    let (tx, rx) = channel();
+    for i in 0..8u64 {
+        let tx = tx.clone();
+        shenango::thread::spawn_detached(move || {
+            for j in 0..1000 {
+                tx.send(i * 1000 + j).unwrap();
+            }
+        });
+    }
+    drop(tx);
+
+    // ends once every sender is gone
+    let sum: u64 = rx.iter().sum();
+    assert_eq!(sum, (0..8000).sum());
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+    println!("mpsc: ok");
+}
+
+fn test_bounded() {
+    let (tx, rx) = sync_channel(4);
+    for i in 0..4 {
+        tx.try_send(i).unwrap();
+    }
+    assert_eq!(tx.try_send(4), Err(TrySendError::Full(4)));
+
+    // senders park while the channel is full, and several receivers share it
+    let producer = shenango::thread::spawn(move || {
+        for i in 4..2000 {
+            tx.send(i).unwrap();
+        }
+    });
+    let consumers: Vec<_> = (0..4)
+        .map(|_| {
+            let rx = rx.clone();
+            shenango::thread::spawn(move || rx.iter().fold(0u64, |sum, i| sum + i))
+        })
+        .collect();
+    drop(rx);
+    producer.join().unwrap();
+    let sum: u64 = consumers.into_iter().map(|c| c.join().unwrap()).sum();
+    assert_eq!(sum, (0..2000).sum());
+    println!("bounded: ok");
+}
+
+fn test_timeout() {
+    let (tx, rx) = channel::<u32>();
+    let start = Instant::now();
+    assert_eq!(
+        rx.recv_timeout(Duration::from_millis(10)),
+        Err(RecvTimeoutError::Timeout)
+    );
+    assert!(start.elapsed() >= Duration::from_millis(10));
+
+    shenango::thread::spawn_detached(move || {
+        shenango::sleep(Duration::from_millis(1));
+        tx.send(7).unwrap();
+    });
+    assert_eq!(rx.recv_timeout(Duration::from_secs(1)), Ok(7));
+    assert_eq!(
+        rx.recv_timeout(Duration::from_secs(1)),
+        Err(RecvTimeoutError::Disconnected)
+    );
+    println!("timeout: ok");
+}
+
+fn test_select() {
+    let (tx1, rx1) = channel::<u32>();
+    let (tx2, rx2) = sync_channel::<u32>(1);
+
+    let mut sel = Select::new();
+    let a = sel.recv(&rx1);
+    let b = sel.recv(&rx2);
+    assert_eq!(sel.try_ready(), None);
+    assert_eq!(sel.ready_timeout(Duration::from_millis(1)), None);
+
+    shenango::thread::spawn_detached(move || tx2.send(2).unwrap());
+    assert_eq!(sel.ready(), b);
+    assert_eq!(rx2.try_recv(), Ok(2));
+
+    tx1.send(1).unwrap();
+    assert_eq!(sel.ready(), a);
+    assert_eq!(rx1.try_recv(), Ok(1));
+
+    // a disconnected receiver is ready, so its receive does not block
+    drop(tx1);
+    assert_eq!(sel.ready(), a);
+    println!("select: ok");
+}
+
+fn main_handler() {
+    test_mpsc();
+    test_bounded();
+    test_timeout();
+    test_select();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
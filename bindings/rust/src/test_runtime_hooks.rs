@@ -0,0 +1,96 @@
+extern crate shenango;
+
+use shenango::runtime::{self, InitStage, RuntimeBuilder};
+use std::env;
+use std::io;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+static GLOBAL_RAN: AtomicBool = AtomicBool::new(false);
+static KTHREADS: AtomicUsize = AtomicUsize::new(0);
+static LATE_RAN: AtomicBool = AtomicBool::new(false);
+static MAIN_RAN: AtomicBool = AtomicBool::new(false);
+
+fn main_handler() {
+    assert!(GLOBAL_RAN.load(Ordering::SeqCst));
+    assert!(LATE_RAN.load(Ordering::SeqCst));
+    assert_eq!(KTHREADS.load(Ordering::SeqCst), runtime::max_cores() as usize);
+    println!("hooks: ok");
+
+    let load = runtime::RuntimeLoad::current();
+    assert!(load.active_cores <= load.max_cores);
+    assert!(load.guaranteed_cores <= load.max_cores);
+    println!("load: {:?}", load);
+}
+
+fn hook_error() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "hook failed")
+}
+
+// Starts the runtime with the hook for `stage` failing, and checks that
+// startup reports that stage without ever running main.
+fn fail_stage(cfgpath: String, stage: InitStage) {
+    let builder = match stage {
+        InitStage::Global => RuntimeBuilder::new().global_init(|| Err(hook_error())),
+        // fail on the last kthread, so the others have finished their setup
+        InitStage::Kthread => RuntimeBuilder::new().kthread_init(|| {
+            if KTHREADS.fetch_add(1, Ordering::SeqCst) + 1 == runtime::max_cores() as usize {
+                return Err(hook_error());
+            }
+            Ok(())
+        }),
+        InitStage::Late => RuntimeBuilder::new().late_init(|| Err(hook_error())),
+        InitStage::Runtime => unreachable!(),
+    };
+    let e = builder
+        .start(cfgpath, || MAIN_RAN.store(true, Ordering::SeqCst))
+        .unwrap_err();
+    assert_eq!(e.init_stage(), Some(stage));
+    assert_eq!(e.kind(), io::ErrorKind::PermissionDenied);
+    assert!(!MAIN_RAN.load(Ordering::SeqCst));
+}
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+
+    // The runtime can only start once per process, so each failing stage
+    // runs in a child.
+    let stage = match args.get(2).map(|s| s.as_str()) {
+        Some("global") => Some(InitStage::Global),
+        Some("kthread") => Some(InitStage::Kthread),
+        Some("late") => Some(InitStage::Late),
+        Some(arg) => panic!("unknown stage {}", arg),
+        None => None,
+    };
+    if let Some(stage) = stage {
+        fail_stage(args[1].clone(), stage);
+        return;
+    }
+    for stage in &["global", "kthread", "late"] {
+        let status = Command::new(env::current_exe().unwrap())
+            .arg(&args[1])
+            .arg(stage)
+            .status()
+            .unwrap();
+        assert!(status.success(), "{} hook failure: {}", stage, status);
+        println!("{} failure: ok", stage);
+    }
+
+    RuntimeBuilder::new()
+        .global_init(|| {
+            GLOBAL_RAN.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .kthread_init(|| {
+            assert!(GLOBAL_RAN.load(Ordering::SeqCst));
+            KTHREADS.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })
+        .late_init(|| {
+            LATE_RAN.store(true, Ordering::SeqCst);
+            Ok(())
+        })
+        .start(args[1].clone(), main_handler)
+        .unwrap();
+}
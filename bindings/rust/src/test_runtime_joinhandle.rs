@@ -2,12 +2,46 @@ extern crate shenango;
 
 use shenango::WaitGroup;
 use std::sync::Arc;
+use std::time::Duration;
 
 const N: usize = 50000;
 const NCORES: usize = 3;
 
+fn test_nonblocking_join() {
+    let wg = Arc::new(WaitGroup::new());
+    wg.add(1);
+    let wg2 = wg.clone();
+    let handle = shenango::thread::spawn(move || {
+        wg2.wait();
+        42
+    });
+
+    assert!(!handle.is_finished());
+    let handle = handle.try_join().err().unwrap();
+    let handle = handle
+        .join_timeout(Duration::from_millis(5))
+        .err()
+        .unwrap();
+
+    // the child is still joinable after a timeout
+    wg.done();
+    assert_eq!(handle.join_timeout(Duration::from_secs(1)).ok().unwrap().unwrap(), 42);
+
+    let handle = shenango::thread::spawn(|| 7);
+    while !handle.is_finished() {
+        shenango::thread::thread_yield();
+    }
+    assert_eq!(handle.try_join().ok().unwrap().unwrap(), 7);
+
+    // and detaching after a timeout lets it exit on its own
+    let handle = shenango::thread::spawn(|| shenango::sleep(Duration::from_millis(5)));
+    assert!(handle.join_timeout(Duration::from_millis(1)).is_err());
+    println!("nonblocking join: ok");
+}
+
 fn main_handler() {
     println!("started main_handler() thread");
+    test_nonblocking_join();
     println!("creating threads with 1us of fake work.");
 
     let wg = Arc::new(WaitGroup::new());
@@ -0,0 +1,65 @@
+#[macro_use]
+extern crate shenango;
+
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+struct Counted(u32);
+impl Drop for Counted {
+    fn drop(&mut self) {
+        DROPS.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+uthread_local! {
+    static COUNTER: Cell<u32> = Cell::new(0);
+    static NAME: String = String::from("uthread");
+    static COUNTED: Counted = Counted(7);
+}
+
+fn test_isolation() {
+    COUNTER.with(|c| c.set(100));
+
+    let join_handles: Vec<_> = (0..8)
+        .map(|i| {
+            shenango::thread::spawn(move || {
+                for _ in 0..1000 {
+                    COUNTER.with(|c| c.set(c.get() + 1));
+                    shenango::thread::thread_yield();
+                }
+                NAME.with(|n| assert_eq!(n, "uthread"));
+                COUNTER.with(|c| c.get() + i)
+            })
+        })
+        .collect();
+    for (i, j) in join_handles.into_iter().enumerate() {
+        assert_eq!(j.join().unwrap(), 1000 + i as u32);
+    }
+
+    COUNTER.with(|c| assert_eq!(c.get(), 100));
+    println!("isolation: ok");
+}
+
+fn test_dtors() {
+    let before = DROPS.load(Ordering::SeqCst);
+    shenango::thread::spawn(|| COUNTED.with(|c| assert_eq!(c.0, 7)))
+        .join()
+        .unwrap();
+    // a uthread that never touches the key never creates a value
+    shenango::thread::spawn(|| ()).join().unwrap();
+    assert_eq!(DROPS.load(Ordering::SeqCst), before + 1);
+    println!("dtors: ok");
+}
+
+fn main_handler() {
+    test_isolation();
+    test_dtors();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
@@ -0,0 +1,58 @@
+extern crate shenango;
+
+use shenango::sync::channel;
+use shenango::thread::{self, PanicPolicy, UthreadPanic};
+use std::env;
+use std::process::Command;
+
+fn main_handler() {
+    let (tx, rx) = channel::<(Option<String>, Option<String>)>();
+    let tx = shenango::sync::Mutex::new(tx);
+    shenango::set_panic_policy(PanicPolicy::Hook(Box::new(move |p: UthreadPanic| {
+        let report = (p.name().map(String::from), p.message().map(String::from));
+        tx.lock().send(report).unwrap();
+    })));
+
+    thread::Builder::new()
+        .name("worker".to_owned())
+        .spawn_detached(|| panic!("worker failed"))
+        .unwrap();
+    let (name, msg) = rx.recv().unwrap();
+    assert_eq!(name.as_ref().map(|n| &n[..]), Some("worker"));
+    assert_eq!(msg.as_ref().map(|m| &m[..]), Some("worker failed"));
+
+    // unnamed uthreads are reported too
+    thread::spawn_detached(|| panic!("{}", 42));
+    assert_eq!(rx.recv().unwrap(), (None, Some("42".to_owned())));
+    println!("hook: ok");
+}
+
+// Runs in a child: a panic in main goes through the policy, then the process
+// exits with a failure status.
+fn main_panics() {
+    shenango::set_panic_policy(PanicPolicy::Hook(Box::new(|p: UthreadPanic| {
+        assert_eq!(p.message(), Some("main failed"));
+        println!("main: reported");
+    })));
+    panic!("main failed");
+}
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    if args.get(2).map(|s| s.as_str()) == Some("main") {
+        shenango::runtime_init(args[1].clone(), main_panics).unwrap();
+        unreachable!("runtime_init returned after main panicked");
+    }
+
+    let out = Command::new(env::current_exe().unwrap())
+        .arg(&args[1])
+        .arg("main")
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&out.stdout).contains("main: reported"));
+    println!("main: ok");
+
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
@@ -0,0 +1,61 @@
+extern crate shenango;
+
+use shenango::percore::{PerCore, PerCoreCounter};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+
+const NTHREADS: usize = 16;
+const NINCS: u64 = 10000;
+
+fn test_counter() {
+    let counter = Arc::new(PerCoreCounter::new());
+    let join_handles: Vec<_> = (0..NTHREADS)
+        .map(|_| {
+            let counter = counter.clone();
+            shenango::thread::spawn(move || {
+                for i in 0..NINCS {
+                    counter.inc();
+                    if i % 100 == 0 {
+                        shenango::thread::thread_yield();
+                    }
+                }
+            })
+        })
+        .collect();
+    for j in join_handles {
+        j.join().unwrap();
+    }
+    assert_eq!(counter.sum(), NTHREADS as u64 * NINCS);
+
+    counter.reset();
+    counter.add(5);
+    assert_eq!(counter.sum(), 5);
+    println!("counter: ok");
+}
+
+fn test_slots() {
+    let slots = PerCore::new(|| AtomicU32::new(u32::max_value()));
+    assert_eq!(slots.len(), shenango::runtime::max_cores() as usize);
+
+    {
+        let slot = slots.get();
+        slot.store(shenango::thread::get_current_affinity(), Ordering::Relaxed);
+    }
+    let core = slots
+        .iter()
+        .position(|s| s.load(Ordering::Relaxed) != u32::max_value())
+        .unwrap();
+    assert_eq!(slots.iter().nth(core).unwrap().load(Ordering::Relaxed), core as u32);
+    println!("slots: ok");
+}
+
+fn main_handler() {
+    test_counter();
+    test_slots();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
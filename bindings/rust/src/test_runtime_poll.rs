@@ -0,0 +1,90 @@
+extern crate shenango;
+
+use shenango::poll::{Poller, Trigger};
+use shenango::tcp::TcpConnection;
+use shenango::udp::UdpConnection;
+use std::io::{Read, Write};
+use std::net::SocketAddrV4;
+use std::sync::Arc;
+
+const NTRIGGERS: usize = 16;
+
+fn test_triggers() {
+    let triggers: Vec<_> = (0..NTRIGGERS).map(|_| Arc::new(Trigger::new())).collect();
+    let poller = Poller::new();
+    let _regs: Vec<_> = triggers
+        .iter()
+        .enumerate()
+        .map(|(i, t)| poller.register(&**t, i).unwrap())
+        .collect();
+
+    // registering a source twice is refused
+    assert!(poller.register(&*triggers[0], NTRIGGERS).is_err());
+
+    let join_handles: Vec<_> = triggers
+        .iter()
+        .map(|t| {
+            let t = t.clone();
+            shenango::thread::spawn(move || t.fire())
+        })
+        .collect();
+
+    let mut seen = vec![false; NTRIGGERS];
+    for _ in 0..NTRIGGERS {
+        let token = poller.wait();
+        assert!(!seen[token]);
+        seen[token] = true;
+    }
+    assert!(seen.iter().all(|&s| s));
+
+    for j in join_handles {
+        j.join().unwrap();
+    }
+
+    // a trigger can fire again once its previous event has been consumed
+    triggers[3].fire();
+    assert_eq!(poller.wait(), 3);
+    println!("triggers: ok");
+}
+
+/// `peer` must echo back whatever it receives, over both TCP and UDP.
+fn test_connections(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let tcp = TcpConnection::dial(any, peer).unwrap();
+    let udp = UdpConnection::dial(any, peer).unwrap();
+    let poller = Poller::new();
+    let _tcp_reg = poller.register(&tcp, 0).unwrap();
+    let _udp_reg = poller.register(&udp, 1).unwrap();
+
+    let mut buf = [0u8; 64];
+    (&tcp).write_all(b"tcp ping").unwrap();
+    assert_eq!(poller.wait(), 0);
+    (&tcp).read_exact(&mut buf[..8]).unwrap();
+    assert_eq!(&buf[..8], b"tcp ping");
+
+    udp.send(b"udp ping").unwrap();
+    assert_eq!(poller.wait(), 1);
+    let n = udp.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..n], b"udp ping");
+
+    // the registrations stay armed for later arrivals
+    (&tcp).write_all(b"again").unwrap();
+    assert_eq!(poller.wait(), 0);
+    (&tcp).read_exact(&mut buf[..5]).unwrap();
+    assert_eq!(&buf[..5], b"again");
+    println!("connections: ok");
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "usage: cfg_file [echo_peer_ip:port]");
+    let peer: Option<SocketAddrV4> = args.get(2).map(|a| a.parse().unwrap());
+    shenango::runtime_init(args[1].clone(), move || {
+        test_triggers();
+        match peer {
+            Some(peer) => test_connections(peer),
+            None => println!("connections: skipped, no echo peer given"),
+        }
+    })
+    .unwrap();
+}
@@ -0,0 +1,75 @@
+extern crate shenango;
+
+use std::panic;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+fn test_borrow() {
+    let input: Vec<u64> = (0..1000).collect();
+    let mut sums = vec![0u64; 8];
+
+    shenango::thread::scope(|s| {
+        for (i, sum) in sums.iter_mut().enumerate() {
+            let input = &input;
+            s.spawn(move || {
+                *sum = input.iter().skip(i).step_by(8).sum();
+                shenango::thread::thread_yield();
+            });
+        }
+    });
+
+    assert_eq!(sums.iter().sum::<u64>(), input.iter().sum::<u64>());
+    println!("borrow: ok");
+}
+
+fn test_join() {
+    let hits = AtomicUsize::new(0);
+    let total = shenango::thread::scope(|s| {
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let hits = &hits;
+                s.spawn(move || {
+                    // nested spawns share the same scope
+                    s.spawn(move || hits.fetch_add(1, Ordering::SeqCst));
+                    i * 2
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).sum::<usize>()
+    });
+    assert_eq!(total, 56);
+    assert_eq!(hits.load(Ordering::SeqCst), 8);
+    println!("join: ok");
+}
+
+fn test_panic() {
+    // a joined panic is handled by the caller
+    let caught = shenango::thread::scope(|s| s.spawn(|| panic!("joined")).join().is_err());
+    assert!(caught);
+
+    // an unjoined one propagates out of the scope, after its siblings finish
+    let done = AtomicUsize::new(0);
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        shenango::thread::scope(|s| {
+            s.spawn(|| panic!("unjoined"));
+            s.spawn(|| {
+                shenango::thread::thread_yield();
+                done.fetch_add(1, Ordering::SeqCst);
+            });
+        })
+    }));
+    assert!(result.is_err());
+    assert_eq!(done.load(Ordering::SeqCst), 1);
+    println!("panic: ok");
+}
+
+fn main_handler() {
+    test_borrow();
+    test_join();
+    test_panic();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
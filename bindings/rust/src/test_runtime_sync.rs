@@ -0,0 +1,123 @@
+extern crate shenango;
+
+use shenango::sync::{Barrier, Condvar, Mutex, RwLock};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+const N: usize = 100000;
+const NTHREADS: usize = 4;
+
+fn test_mutex() {
+    let counter = Arc::new(Mutex::new(0));
+
+    let join_handles: Vec<_> = (0..NTHREADS)
+        .map(|_| {
+            let counter = counter.clone();
+            shenango::thread::spawn(move || {
+                for _ in 0..N {
+                    *counter.lock() += 1;
+                }
+            })
+        })
+        .collect();
+
+    for j in join_handles {
+        j.join().unwrap();
+    }
+
+    assert_eq!(*counter.lock(), N * NTHREADS);
+    let guard = counter.lock();
+    assert!(counter.try_lock().is_none());
+    drop(guard);
+    assert!(counter.try_lock().is_some());
+    println!("mutex: ok");
+}
+
+fn test_condvar() {
+    let pair = Arc::new((Mutex::new(false), Condvar::new()));
+
+    let pair2 = pair.clone();
+    let j = shenango::thread::spawn(move || {
+        let &(ref lock, ref cvar) = &*pair2;
+        *lock.lock() = true;
+        cvar.notify_one();
+    });
+
+    let &(ref lock, ref cvar) = &*pair;
+    let started = cvar.wait_while(lock.lock(), |started| !*started);
+    assert!(*started);
+    drop(started);
+
+    j.join().unwrap();
+    println!("condvar: ok");
+}
+
+fn test_rwlock() {
+    let table = Arc::new(RwLock::new(vec![0; NTHREADS]));
+
+    let join_handles: Vec<_> = (0..NTHREADS)
+        .map(|i| {
+            let table = table.clone();
+            shenango::thread::spawn(move || {
+                for n in 0..N {
+                    assert_eq!(table.read()[i], n);
+                    table.write()[i] += 1;
+                }
+            })
+        })
+        .collect();
+
+    for j in join_handles {
+        j.join().unwrap();
+    }
+
+    assert!(table.read().iter().all(|&c| c == N));
+    let r1 = table.read();
+    let r2 = table.try_read();
+    assert!(r2.is_some());
+    assert!(table.try_write().is_none());
+    drop(r1);
+    drop(r2);
+    assert!(table.try_write().is_some());
+    println!("rwlock: ok");
+}
+
+fn test_barrier() {
+    const ROUNDS: usize = 100;
+    let barrier = Arc::new(Barrier::new(NTHREADS));
+    let leaders = Arc::new(AtomicUsize::new(0));
+
+    let join_handles: Vec<_> = (0..NTHREADS)
+        .map(|_| {
+            let barrier = barrier.clone();
+            let leaders = leaders.clone();
+            shenango::thread::spawn(move || {
+                for _ in 0..ROUNDS {
+                    if barrier.wait().is_leader() {
+                        leaders.fetch_add(1, Ordering::SeqCst);
+                    }
+                }
+            })
+        })
+        .collect();
+
+    for j in join_handles {
+        j.join().unwrap();
+    }
+
+    assert_eq!(leaders.load(Ordering::SeqCst), ROUNDS);
+    println!("barrier: ok");
+}
+
+fn main_handler() {
+    test_mutex();
+    test_condvar();
+    test_rwlock();
+    test_barrier();
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
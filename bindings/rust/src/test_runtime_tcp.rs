@@ -0,0 +1,91 @@
+extern crate shenango;
+
+use shenango::runtime;
+use shenango::tcp::TcpConnection;
+use std::io::{self, IoSlice, IoSliceMut, Read, Write};
+use std::net::SocketAddrV4;
+
+/// `peer` must echo back whatever it receives.
+fn test_vectored(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let mut tcp = TcpConnection::dial(any, peer).unwrap();
+
+    // trailing and interior empty vectors must not hold back the data
+    let bufs = [
+        IoSlice::new(b"ab"),
+        IoSlice::new(b""),
+        IoSlice::new(b"cde"),
+        IoSlice::new(b""),
+    ];
+    assert_eq!(tcp.write_vectored(&bufs).unwrap(), 5);
+
+    let mut head = [0u8; 1];
+    let mut tail = [0u8; 4];
+    let mut n = 0;
+    while n < 5 {
+        let (h, t) = (&mut head[..], &mut tail[..]);
+        let mut bufs = if n == 0 {
+            vec![IoSliceMut::new(h), IoSliceMut::new(t)]
+        } else {
+            vec![IoSliceMut::new(&mut t[n - 1..])]
+        };
+        let read = tcp.read_vectored(&mut bufs).unwrap();
+        assert!(read > 0, "connection closed early");
+        n += read;
+    }
+    assert_eq!(&head, b"a");
+    assert_eq!(&tail, b"bcde");
+    println!("vectored: ok");
+}
+
+fn echo(tcp: &mut TcpConnection, msg: &[u8]) {
+    tcp.write_all(msg).unwrap();
+    let mut buf = vec![0u8; msg.len()];
+    let mut n = 0;
+    while n < buf.len() {
+        let read = tcp.read(&mut buf[n..]).unwrap();
+        assert!(read > 0, "connection closed early");
+        n += read;
+    }
+    assert_eq!(&buf[..], msg);
+}
+
+fn test_dial_affinity_invalid() {
+    let peer = "10.0.0.1:1".parse().unwrap();
+    match TcpConnection::dial_affinity(runtime::max_cores(), peer) {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+        Ok(_) => panic!("dialed on a kthread that does not exist"),
+    }
+    println!("dial_affinity invalid core: ok");
+}
+
+/// `peer` must echo back whatever it receives.
+fn test_dial_affinity(peer: SocketAddrV4) {
+    let core = runtime::max_cores() - 1;
+    let mut tcp = TcpConnection::dial_affinity(core, peer).unwrap();
+    assert_eq!(tcp.remote_addr(), peer);
+    echo(&mut tcp, b"affinity");
+
+    let mut like = TcpConnection::dial_like(&tcp, peer).unwrap();
+    assert_eq!(like.remote_addr(), peer);
+    assert_ne!(like.local_addr().port(), tcp.local_addr().port());
+    echo(&mut like, b"like");
+    println!("dial_affinity: ok");
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "usage: cfg_file [echo_peer_ip:port]");
+    let peer: Option<SocketAddrV4> = args.get(2).map(|a| a.parse().unwrap());
+    shenango::runtime_init(args[1].clone(), move || {
+        test_dial_affinity_invalid();
+        match peer {
+            Some(peer) => {
+                test_vectored(peer);
+                test_dial_affinity(peer);
+            }
+            None => println!("vectored, dial_affinity: skipped, no echo peer given"),
+        }
+    })
+    .unwrap();
+}
@@ -0,0 +1,94 @@
+extern crate shenango;
+
+use shenango::tcp::TcpConnection;
+use shenango::udp::UdpConnection;
+use std::io::{self, Read, Write};
+use std::net::SocketAddrV4;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_millis(10);
+
+fn assert_timed_out<T>(res: io::Result<T>, start: Instant) {
+    match res {
+        Err(e) => assert_eq!(e.kind(), io::ErrorKind::TimedOut),
+        Ok(_) => panic!("expected a timeout"),
+    }
+    assert!(start.elapsed() >= TIMEOUT);
+}
+
+fn read_exact(tcp: &mut TcpConnection, buf: &mut [u8]) {
+    let mut n = 0;
+    while n < buf.len() {
+        let read = tcp.read(&mut buf[n..]).unwrap();
+        assert!(read > 0, "connection closed early");
+        n += read;
+    }
+}
+
+/// `peer` must echo back whatever it receives, over both TCP and UDP.
+fn test_read_timeout(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let mut buf = [0u8; 64];
+
+    let mut tcp = TcpConnection::dial(any, peer).unwrap();
+    tcp.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(tcp.read_timeout().unwrap(), Some(TIMEOUT));
+    let start = Instant::now();
+    assert_timed_out(tcp.read(&mut buf), start);
+
+    // the connection is still usable after a timeout
+    tcp.write_all(b"tcp ping").unwrap();
+    read_exact(&mut tcp, &mut buf[..8]);
+    assert_eq!(&buf[..8], b"tcp ping");
+
+    let udp = UdpConnection::dial(any, peer).unwrap();
+    udp.set_read_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(udp.read_timeout().unwrap(), Some(TIMEOUT));
+    let start = Instant::now();
+    assert_timed_out(udp.read_from(&mut buf), start);
+
+    assert_eq!(udp.write_to(b"udp ping", peer).unwrap(), 8);
+    assert_eq!(udp.read_from(&mut buf).unwrap(), (8, peer));
+    assert_eq!(&buf[..8], b"udp ping");
+
+    udp.set_read_timeout(None).unwrap();
+    assert_eq!(udp.read_timeout().unwrap(), None);
+    println!("read timeout: ok");
+}
+
+/// Never reads the echoes, so the peer stops reading too and the send window
+/// eventually fills.
+fn test_write_timeout(peer: SocketAddrV4) {
+    let any = "0.0.0.0:0".parse().unwrap();
+    let mut tcp = TcpConnection::dial(any, peer).unwrap();
+    tcp.set_write_timeout(Some(TIMEOUT)).unwrap();
+    assert_eq!(tcp.write_timeout().unwrap(), Some(TIMEOUT));
+
+    let chunk = vec![0u8; 64 * 1024];
+    for _ in 0..16 * 1024 {
+        let start = Instant::now();
+        match tcp.write(&chunk) {
+            Ok(n) => assert!(n > 0),
+            Err(e) => {
+                assert_timed_out(Err::<(), _>(e), start);
+                println!("write timeout: ok");
+                return;
+            }
+        }
+    }
+    panic!("the send window never filled");
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "usage: cfg_file [echo_peer_ip:port]");
+    let peer: Option<SocketAddrV4> = args.get(2).map(|a| a.parse().unwrap());
+    shenango::runtime_init(args[1].clone(), move || match peer {
+        Some(peer) => {
+            test_read_timeout(peer);
+            test_write_timeout(peer);
+        }
+        None => println!("timeouts: skipped, no echo peer given"),
+    })
+    .unwrap();
+}
@@ -0,0 +1,61 @@
+extern crate shenango;
+
+use shenango::storage::{self, StorageQueue};
+use std::time::{Duration, Instant};
+
+const QUEUE_DEPTH: usize = 32;
+const NREQS: usize = 100000;
+
+fn test_roundtrip(q: &StorageQueue) {
+    let bsize = q.block_size();
+    let data: Vec<u8> = (0..bsize * 4).map(|i| i as u8).collect();
+    q.write(&data, 8).unwrap().wait().unwrap();
+    let read = q.read(8, 4).unwrap().wait().unwrap();
+    assert_eq!(read.data, data);
+
+    // out of range and partial-block requests are refused up front
+    assert!(q.read(q.num_blocks(), 1).is_err());
+    assert!(q.write(&data[1..], 8).is_err());
+    println!("roundtrip: ok");
+}
+
+fn bench_iops(q: &StorageQueue) {
+    let mut inflight = Vec::with_capacity(QUEUE_DEPTH);
+    let mut latencies = Vec::with_capacity(NREQS);
+    let start = Instant::now();
+    for i in 0..NREQS {
+        if inflight.len() == QUEUE_DEPTH {
+            let (_, res) = storage::wait_any(&mut inflight).unwrap();
+            latencies.push(res.unwrap().latency);
+        }
+        let lba = (i as u64 * 7919) % q.num_blocks();
+        inflight.push(q.read(lba, 1).unwrap());
+    }
+    for res in storage::wait_all(inflight) {
+        latencies.push(res.unwrap().latency);
+    }
+    let elapsed = start.elapsed();
+
+    latencies.sort();
+    let p = |q: f64| latencies[((latencies.len() - 1) as f64 * q) as usize];
+    let total: Duration = latencies.iter().sum();
+    println!(
+        "iops: {:.0}, latency mean {:?} p50 {:?} p99 {:?}",
+        NREQS as f64 / (elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9),
+        total / NREQS as u32,
+        p(0.5),
+        p(0.99)
+    );
+}
+
+fn main_handler() {
+    let q = StorageQueue::new().expect("storage must be enabled in the config");
+    test_roundtrip(&q);
+    bench_iops(&q);
+}
+
+fn main() {
+    let args: Vec<_> = ::std::env::args().collect();
+    assert!(args.len() >= 2, "arg must be config file");
+    shenango::runtime_init(args[1].clone(), main_handler).unwrap();
+}
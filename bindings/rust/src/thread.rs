@@ -1,24 +1,143 @@
 use std::any::Any;
-use std::cell::UnsafeCell;
-use std::os::raw::c_void;
-use std::{mem, panic, ptr};
+use std::cell::{RefCell, UnsafeCell};
+use std::os::raw::{c_uint, c_void};
+use std::io;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+use std::{fmt, mem, panic, process, ptr};
 
 use super::*;
+use executor::Parker;
+use timer::Timeout;
 
 extern "C" {
     #[link_name = "__self"]
     #[thread_local]
     static mut __self: *mut ffi::thread_t;
+
+    #[link_name = "kthread_idx"]
+    #[thread_local]
+    static kthread_idx: c_uint;
 }
 
 pub(crate) fn thread_self() -> *mut ffi::thread_t {
     unsafe { __self }
 }
 
+/// Returns the index of the kthread the caller is running on, in
+/// `0..runtime::max_cores()`. Uthreads migrate, so the answer is only stable
+/// while preemption is disabled.
+pub fn get_current_affinity() -> u32 {
+    unsafe { kthread_idx }
+}
+
 pub fn thread_yield() {
     unsafe { ffi::thread_yield() }
 }
 
+/// What happens when a detached uthread or the main closure panics. Panics
+/// in joinable uthreads are returned by `join` instead. Once a panic in the
+/// main closure has been handled, the process exits with a failure status.
+pub enum PanicPolicy {
+    /// Print the panic and let the rest of the program continue. The default.
+    Log,
+    /// Print the panic and abort the process.
+    Abort,
+    /// Pass the panic to a user hook, which runs on the panicked uthread.
+    Hook(Box<dyn Fn(UthreadPanic) + Send + Sync + 'static>),
+}
+
+/// A panic that escaped a detached uthread.
+pub struct UthreadPanic {
+    payload: Box<dyn Any + Send + 'static>,
+    name: Option<String>,
+    id: usize,
+    kthread: u32,
+}
+impl UthreadPanic {
+    pub fn payload(&self) -> &(dyn Any + Send + 'static) {
+        &*self.payload
+    }
+
+    pub fn into_payload(self) -> Box<dyn Any + Send + 'static> {
+        self.payload
+    }
+
+    /// The panic message, if it was a string.
+    pub fn message(&self) -> Option<&str> {
+        panic_message(&*self.payload)
+    }
+
+    /// The name given by `Builder::name`, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|n| &n[..])
+    }
+
+    /// Identifies the uthread among those currently alive. Ids are reused
+    /// once a uthread exits.
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// The kthread the uthread panicked on.
+    pub fn kthread(&self) -> u32 {
+        self.kthread
+    }
+}
+impl fmt::Display for UthreadPanic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name {
+            Some(ref name) => write!(f, "uthread '{}'", name)?,
+            None => write!(f, "uthread {:#x}", self.id)?,
+        }
+        write!(
+            f,
+            " panicked on kthread {}: {}",
+            self.kthread,
+            self.message().unwrap_or("Box<Any>")
+        )
+    }
+}
+
+static PANIC_POLICY: AtomicPtr<PanicPolicy> = AtomicPtr::new(0 as *mut PanicPolicy);
+
+/// Sets the panic policy for the whole process. Meant to be called once at
+/// startup, since a replaced policy is never freed.
+pub fn set_panic_policy(policy: PanicPolicy) {
+    PANIC_POLICY.store(Box::into_raw(Box::new(policy)), Ordering::Release);
+}
+
+pub(crate) fn panic_message<'a>(payload: &'a (dyn Any + Send + 'static)) -> Option<&'a str> {
+    match payload.downcast_ref::<&'static str>() {
+        Some(s) => Some(*s),
+        None => payload.downcast_ref::<String>().map(|s| &s[..]),
+    }
+}
+
+pub(crate) fn report_panic(payload: Box<dyn Any + Send + 'static>) {
+    let report = UthreadPanic {
+        payload: payload,
+        name: current_name(),
+        id: thread_self() as usize,
+        kthread: get_current_affinity(),
+    };
+    let policy = PANIC_POLICY.load(Ordering::Acquire);
+    match unsafe { policy.as_ref() } {
+        None | Some(&PanicPolicy::Log) => eprintln!("{}", report),
+        Some(&PanicPolicy::Abort) => {
+            eprintln!("{}", report);
+            process::abort();
+        }
+        Some(&PanicPolicy::Hook(ref hook)) => {
+            // A panicking hook would otherwise unwind out of the trampoline.
+            if panic::catch_unwind(panic::AssertUnwindSafe(|| hook(report))).is_err() {
+                process::abort();
+            }
+        }
+    }
+}
+
 pub(crate) extern "C" fn trampoline<F>(arg: *mut c_void)
 where
     F: FnOnce(),
@@ -26,7 +145,10 @@ where
 {
     let f = arg as *mut F;
     let f: F = unsafe { mem::transmute_copy(&*f as &F) };
-    let _result = panic::catch_unwind(panic::AssertUnwindSafe(move || f()));
+    if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(move || f())) {
+        report_panic(payload);
+    }
+    run_local_dtors();
 }
 
 pub(crate) extern "C" fn box_trampoline<F>(arg: *mut c_void)
@@ -35,7 +157,10 @@ where
     F: Send + 'static,
 {
     let f = unsafe { Box::from_raw(arg as *mut F) };
-    let _result = panic::catch_unwind(panic::AssertUnwindSafe(move || f()));
+    if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(move || f())) {
+        report_panic(payload);
+    }
+    run_local_dtors();
 }
 pub(crate) extern "C" fn base_trampoline<T, F>(arg: *mut c_void)
 where
@@ -48,6 +173,7 @@ where
     let base = unsafe { &mut *base };
     let f: F = base.f.take().unwrap();
     let result = panic::catch_unwind(panic::AssertUnwindSafe(move || f()));
+    run_local_dtors();
 
     // Set return value.
     let d = unsafe { &mut *base.join_data.get() };
@@ -55,16 +181,20 @@ where
     d.data = Some(result);
 
     // If another thread called detach on this one, exit immediately.
-    if d.done && d.waiter.is_null() {
+    if d.done && d.waiter.is_null() && d.joiner.is_none() {
         preempt_enable();
         return;
     }
 
     // If another thread called join on this one, wake it now.
     if d.done {
-        let waiter = d.waiter;
-        assert!(!waiter.is_null());
-        unsafe { ffi::thread_ready(waiter) };
+        if let Some(joiner) = d.joiner.take() {
+            joiner.unpark();
+        } else {
+            let waiter = d.waiter;
+            assert!(!waiter.is_null());
+            unsafe { ffi::thread_ready(waiter) };
+        }
     }
 
     // Don't exit until the parent thread calls join or detach.
@@ -77,6 +207,9 @@ struct JoinData<T: Send + 'static> {
     lock: SpinLock,
     done: bool,
     waiter: *mut ffi::thread_t,
+    /// Set instead of `waiter` by a joiner that may give up, see
+    /// `JoinHandle::join_timeout`.
+    joiner: Option<Arc<Parker>>,
     data: Option<Result<T, Box<dyn Any + Send + 'static>>>,
 }
 
@@ -108,12 +241,68 @@ impl<T: Send + 'static> JoinHandle<T> {
                 (&*join_data).lock.lock_np();
             }
 
-            // Extract the return value, and let the other thread exit.
-            let data = (&mut *join_data).data.take().unwrap();
-            assert!((&*join_data).waiter != ptr::null_mut());
-            ffi::thread_ready((&*join_data).waiter);
-            preempt_enable();
-            data
+            Self::finish(join_data)
+        }
+    }
+
+    /// Extracts the return value, and lets the finished thread exit. Called
+    /// with the lock held.
+    unsafe fn finish(join_data: *mut JoinData<T>) -> Result<T, Box<dyn Any + Send + 'static>> {
+        let data = (&mut *join_data).data.take().unwrap();
+        assert!((&*join_data).waiter != ptr::null_mut());
+        ffi::thread_ready((&*join_data).waiter);
+        preempt_enable();
+        data
+    }
+
+    /// Returns true once the thread has returned (or panicked), so that
+    /// `join` would not block.
+    pub fn is_finished(&self) -> bool {
+        let join_data = unsafe { &*(&*self.join_data).get() };
+        join_data.lock.lock_np();
+        let finished = join_data.data.is_some();
+        join_data.lock.unlock_np();
+        finished
+    }
+
+    /// Joins the thread if it has finished, or hands the handle back.
+    pub fn try_join(self) -> Result<Result<T, Box<dyn Any + Send + 'static>>, Self> {
+        if self.is_finished() {
+            Ok(self.join())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Like `join`, but gives up after `timeout` and hands the handle back,
+    /// leaving the thread to be joined or detached later.
+    pub fn join_timeout(
+        mut self,
+        timeout: Duration,
+    ) -> Result<Result<T, Box<dyn Any + Send + 'static>>, Self> {
+        let parker = Arc::new(Parker::new());
+        let timeout = Timeout::new(Instant::now() + timeout, &parker);
+        unsafe {
+            let join_data = (&*self.join_data).get();
+            (&*join_data).lock.lock_np();
+            loop {
+                if (&*join_data).data.is_some() {
+                    self.join_data = ptr::null();
+                    return Ok(Self::finish(join_data));
+                }
+                if timeout.expired() {
+                    (&mut *join_data).done = false;
+                    (&mut *join_data).joiner = None;
+                    (&*join_data).lock.unlock_np();
+                    return Err(self);
+                }
+
+                (&mut *join_data).done = true;
+                (&mut *join_data).joiner = Some(parker.clone());
+                (&*join_data).lock.unlock_np();
+                parker.park();
+                (&*join_data).lock.lock_np();
+            }
         }
     }
 }
@@ -136,7 +325,9 @@ impl<T: Send + 'static> Drop for JoinHandle<T> {
     }
 }
 
-pub fn spawn_detached<F>(mut f: F)
+/// Creates a parked uthread that will run `f`, leaving it to the caller to
+/// make it runnable.
+fn create_detached<F>(mut f: F) -> *mut ffi::thread_t
 where
     F: FnOnce(),
     F: Send + 'static,
@@ -158,11 +349,21 @@ where
             mem::size_of::<F>(),
         );
         mem::forget(f);
-        ffi::thread_ready(th)
-    };
+    }
+    th
 }
 
-pub fn spawn<T, F>(f: F) -> JoinHandle<T>
+pub fn spawn_detached<F>(f: F)
+where
+    F: FnOnce(),
+    F: Send + 'static,
+{
+    let th = create_detached(f);
+    unsafe { ffi::thread_ready(th) };
+}
+
+/// Like `create_detached`, but returns a handle for joining the uthread.
+fn create<T, F>(f: F) -> (*mut ffi::thread_t, JoinHandle<T>)
 where
     F: FnOnce() -> T,
     F: Send + 'static,
@@ -187,6 +388,7 @@ where
             lock: SpinLock::new(),
             done: false,
             waiter: ptr::null_mut(),
+            joiner: None,
             data: None,
         }),
     };
@@ -199,13 +401,430 @@ where
     }
     mem::forget(base);
 
-    // Start the thread.
-    unsafe { ffi::thread_ready(th) };
-
     // Construct a JoinHandle for the new thread.
     #[cfg_attr(rustfmt, rustfmt_skip)]
     let &mut StackBase { ref mut join_data, .. } = unsafe { &mut *buf };
-    JoinHandle {
+    let handle = JoinHandle {
         join_data: join_data as *const UnsafeCell<JoinData<T>>,
+    };
+    (th, handle)
+}
+
+pub fn spawn<T, F>(f: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T,
+    F: Send + 'static,
+    T: Send + 'static,
+{
+    let (th, handle) = create(f);
+    unsafe { ffi::thread_ready(th) };
+    handle
+}
+
+/// Tracks the uthreads spawned in a `scope` that have yet to finish.
+struct ScopeData {
+    lock: SpinLock,
+    running: UnsafeCell<usize>,
+    waiter: UnsafeCell<*mut ffi::thread_t>,
+    panicked: AtomicBool,
+}
+impl ScopeData {
+    fn increment(&self) {
+        self.lock.lock_np();
+        unsafe { *self.running.get() += 1 };
+        self.lock.unlock_np();
+    }
+
+    fn decrement(&self, panicked: bool) {
+        if panicked {
+            self.panicked.store(true, Ordering::Relaxed);
+        }
+
+        self.lock.lock_np();
+        let waiter = unsafe {
+            *self.running.get() -= 1;
+            if *self.running.get() == 0 {
+                mem::replace(&mut *self.waiter.get(), ptr::null_mut())
+            } else {
+                ptr::null_mut()
+            }
+        };
+        self.lock.unlock_np();
+
+        if !waiter.is_null() {
+            unsafe { ffi::thread_ready(waiter) };
+        }
+    }
+
+    /// Parks until every uthread in the scope has finished.
+    fn wait(&self) {
+        loop {
+            self.lock.lock_np();
+            unsafe {
+                if *self.running.get() == 0 {
+                    self.lock.unlock_np();
+                    return;
+                }
+                *self.waiter.get() = thread_self();
+                ffi::thread_park_and_unlock_np(self.lock.as_raw());
+            }
+        }
+    }
+}
+unsafe impl Send for ScopeData {}
+unsafe impl Sync for ScopeData {}
+
+/// The result of a scoped uthread. Once the last reference is dropped, the
+/// uthread no longer touches anything borrowed from the scope, so dropping it
+/// is what releases the scope.
+struct Packet<'scope, T> {
+    scope: Arc<ScopeData>,
+    result: UnsafeCell<Option<Result<T, Box<dyn Any + Send + 'static>>>>,
+    _marker: PhantomData<&'scope ()>,
+}
+impl<'scope, T> Drop for Packet<'scope, T> {
+    fn drop(&mut self) {
+        // A panic that nobody joined is reported when the scope ends.
+        let unhandled = match unsafe { &*self.result.get() } {
+            Some(Err(_)) => true,
+            _ => false,
+        };
+        let result = unsafe { (*self.result.get()).take() };
+        let _ = panic::catch_unwind(panic::AssertUnwindSafe(move || drop(result)));
+        self.scope.decrement(unhandled);
+    }
+}
+unsafe impl<'scope, T: Send> Send for Packet<'scope, T> {}
+unsafe impl<'scope, T: Send> Sync for Packet<'scope, T> {}
+
+/// A scope for spawning uthreads that borrow from the caller. See `scope`.
+pub struct Scope<'scope, 'env: 'scope> {
+    data: Arc<ScopeData>,
+    scope: PhantomData<&'scope mut &'scope ()>,
+    env: PhantomData<&'env mut &'env ()>,
+}
+impl<'scope, 'env> Scope<'scope, 'env> {
+    /// Spawns a uthread that may borrow anything that outlives the scope.
+    /// Unlike `spawn`, a panic that is never observed through `join` makes
+    /// the whole scope panic.
+    pub fn spawn<T, F>(&'scope self, f: F) -> ScopedJoinHandle<'scope, T>
+    where
+        F: FnOnce() -> T,
+        F: Send + 'scope,
+        T: Send + 'scope,
+    {
+        let packet = Arc::new(Packet {
+            scope: self.data.clone(),
+            result: UnsafeCell::new(None),
+            _marker: PhantomData,
+        });
+        let their_packet = packet.clone();
+        let main = move || {
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(f));
+            unsafe { *their_packet.result.get() = Some(result) };
+            drop(their_packet);
+        };
+
+        // `scope` does not return until every packet is dropped, so nothing
+        // the closure borrows can go away while it runs.
+        let main: Box<dyn FnOnce() + Send + 'scope> = Box::new(main);
+        let main: Box<dyn FnOnce() + Send + 'static> = unsafe { mem::transmute(main) };
+
+        self.data.increment();
+        ScopedJoinHandle {
+            handle: spawn(main),
+            packet: packet,
+        }
+    }
+}
+
+/// Handle to a uthread started with `Scope::spawn`.
+pub struct ScopedJoinHandle<'scope, T: 'scope> {
+    handle: JoinHandle<()>,
+    packet: Arc<Packet<'scope, T>>,
+}
+impl<'scope, T> ScopedJoinHandle<'scope, T> {
+    pub fn join(self) -> Result<T, Box<dyn Any + Send + 'static>> {
+        let ScopedJoinHandle { handle, packet } = self;
+        let _ = handle.join();
+        unsafe { (*packet.result.get()).take() }.unwrap()
+    }
+}
+
+/// Runs `f` with a `Scope` for spawning uthreads that borrow from the
+/// caller's stack, in the style of `std::thread::scope`. Every uthread spawned
+/// in the scope is joined before this returns. If `f` panics, or any of those
+/// uthreads panicked without being joined, so does this.
+///
+/// ```ignore
+/// let mut counts = vec![0; 8];
+/// thread::scope(|s| {
+///     for c in counts.iter_mut() {
+///         s.spawn(move || *c += 1);
+///     }
+/// });
+/// ```
+pub fn scope<'env, F, T>(f: F) -> T
+where
+    F: for<'scope> FnOnce(&'scope Scope<'scope, 'env>) -> T,
+{
+    let scope = Scope {
+        data: Arc::new(ScopeData {
+            lock: SpinLock::new(),
+            running: UnsafeCell::new(0),
+            waiter: UnsafeCell::new(ptr::null_mut()),
+            panicked: AtomicBool::new(false),
+        }),
+        scope: PhantomData,
+        env: PhantomData,
+    };
+
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(&scope)));
+    scope.data.wait();
+
+    match result {
+        Err(e) => panic::resume_unwind(e),
+        Ok(_) if scope.data.panicked.load(Ordering::Relaxed) => {
+            panic!("a scoped uthread panicked")
+        }
+        Ok(result) => result,
+    }
+}
+
+/// Declares a `UthreadLocal` key. Each uthread gets its own copy of the
+/// value, created on first access and dropped when the uthread exits.
+///
+/// ```ignore
+/// uthread_local!(static COUNTER: Cell<u32> = Cell::new(0));
+///
+/// COUNTER.with(|c| c.set(c.get() + 1));
+/// ```
+#[macro_export]
+macro_rules! uthread_local {
+    () => {};
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr; $($rest:tt)*) => {
+        $crate::uthread_local!($(#[$attr])* $vis static $name: $t = $init);
+        $crate::uthread_local!($($rest)*);
+    };
+    ($(#[$attr:meta])* $vis:vis static $name:ident: $t:ty = $init:expr) => {
+        $(#[$attr])* $vis static $name: $crate::thread::UthreadLocal<$t> = {
+            fn __init() -> $t {
+                $init
+            }
+            $crate::thread::UthreadLocal {
+                __init: __init,
+                __key: ::std::sync::atomic::AtomicUsize::new(0),
+            }
+        };
+    };
+}
+
+/// Per-uthread values, hung off the runtime's single uthread-specific slot
+/// and indexed by key.
+struct LocalSlots {
+    values: Vec<Option<Box<dyn Any>>>,
+}
+
+static NEXT_LOCAL_KEY: AtomicUsize = AtomicUsize::new(1);
+
+fn local_slots() -> *mut LocalSlots {
+    let mut slots = unsafe { ffi::get_uthread_specific() } as *mut LocalSlots;
+    if slots.is_null() {
+        slots = Box::into_raw(Box::new(LocalSlots { values: Vec::new() }));
+        unsafe { ffi::set_uthread_specific(slots as u64) };
+    }
+    slots
+}
+
+/// Drops the calling uthread's local values. Destructors may touch other
+/// keys, which then get a fresh value that is dropped in a later pass.
+pub(crate) fn run_local_dtors() {
+    loop {
+        let slots = unsafe { ffi::get_uthread_specific() } as *mut LocalSlots;
+        if slots.is_null() {
+            return;
+        }
+        unsafe { ffi::set_uthread_specific(0) };
+        let slots = unsafe { Box::from_raw(slots) };
+        let _result = panic::catch_unwind(panic::AssertUnwindSafe(move || drop(slots)));
+    }
+}
+
+/// A key for uthread-local storage, declared with `uthread_local!`.
+///
+/// Unlike `std::thread_local!`, whose values belong to the kthread and so
+/// change underneath a uthread that migrates, values here follow the uthread.
+/// They are dropped when a uthread spawned through this crate exits.
+pub struct UthreadLocal<T: 'static> {
+    #[doc(hidden)]
+    pub __init: fn() -> T,
+    #[doc(hidden)]
+    pub __key: AtomicUsize,
+}
+impl<T: 'static> UthreadLocal<T> {
+    fn key(&self) -> usize {
+        let key = self.__key.load(Ordering::Acquire);
+        if key != 0 {
+            return key - 1;
+        }
+        let new = NEXT_LOCAL_KEY.fetch_add(1, Ordering::Relaxed);
+        match self
+            .__key
+            .compare_exchange(0, new, Ordering::AcqRel, Ordering::Acquire)
+        {
+            Ok(_) => new - 1,
+            Err(existing) => existing - 1,
+        }
+    }
+
+    /// Runs `f` with a reference to this uthread's value, initializing it
+    /// first if needed.
+    pub fn with<F, R>(&'static self, f: F) -> R
+    where
+        F: FnOnce(&T) -> R,
+    {
+        let key = self.key();
+        let slots = local_slots();
+
+        let values = unsafe { &(*slots).values };
+        let existing = values
+            .get(key)
+            .and_then(|v| v.as_ref())
+            .map(|v| &**v as *const dyn Any);
+        let value = match existing {
+            Some(v) => v,
+            None => {
+                // The initializer may itself use uthread locals, so no
+                // reference into the slots is held across it.
+                let v: Box<dyn Any> = Box::new((self.__init)());
+                let slots = local_slots();
+                let values = unsafe { &mut (*slots).values };
+                if values.len() <= key {
+                    values.resize_with(key + 1, || None);
+                }
+                if values[key].is_none() {
+                    values[key] = Some(v);
+                }
+                &**values[key].as_ref().unwrap() as *const dyn Any
+            }
+        };
+
+        // Values are boxed, so they stay put as other keys are added.
+        f(unsafe { &*value }.downcast_ref::<T>().unwrap())
+    }
+}
+
+uthread_local!(static THREAD_NAME: RefCell<Option<String>> = RefCell::new(None));
+
+/// Returns the name given to the calling uthread by `Builder::name`.
+pub fn current_name() -> Option<String> {
+    THREAD_NAME.with(|name| name.borrow().clone())
+}
+
+/// Configures a uthread before spawning it.
+///
+/// ```ignore
+/// thread::Builder::new()
+///     .name("reply".to_owned())
+///     .run_next(true)
+///     .spawn_detached(move || send_reply(req))?;
+/// ```
+#[derive(Debug, Default)]
+pub struct Builder {
+    name: Option<String>,
+    run_next: bool,
+    kthread: Option<u32>,
+}
+impl Builder {
+    pub fn new() -> Self {
+        Builder::default()
+    }
+
+    /// Names the uthread, for debugging. See `current_name`.
+    pub fn name(mut self, name: String) -> Self {
+        self.name = Some(name);
+        self
+    }
+
+    /// Queues the uthread at the head of the runqueue, so that it runs before
+    /// work that is already waiting. Only applies when the uthread is queued
+    /// on the calling kthread.
+    pub fn run_next(mut self, run_next: bool) -> Self {
+        self.run_next = run_next;
+        self
+    }
+
+    /// Queues the uthread on kthread `kthread` (see `get_current_affinity`)
+    /// rather than the calling one. Another kthread may still steal it if
+    /// left waiting.
+    pub fn kthread(mut self, kthread: u32) -> Self {
+        self.kthread = Some(kthread);
+        self
+    }
+
+    fn wrap<T, F>(name: Option<String>, f: F) -> impl FnOnce() -> T + Send + 'static
+    where
+        F: FnOnce() -> T,
+        F: Send + 'static,
+    {
+        move || {
+            if name.is_some() {
+                THREAD_NAME.with(|n| *n.borrow_mut() = name);
+            }
+            f()
+        }
+    }
+
+    fn check(&self) -> io::Result<()> {
+        match self.kthread {
+            Some(k) if k >= unsafe { ffi::nrks } => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no such kthread",
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    fn ready(&self, th: *mut ffi::thread_t) -> io::Result<()> {
+        let local = match self.kthread {
+            Some(k) => k == get_current_affinity(),
+            None => true,
+        };
+        unsafe {
+            if local && self.run_next {
+                ffi::thread_ready_head(th);
+            } else if let Some(k) = self.kthread {
+                let ret = ffi::thread_ready_on(th, k as c_uint);
+                if ret < 0 {
+                    return Err(io_error(ret));
+                }
+            } else {
+                ffi::thread_ready(th);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn spawn<T, F>(mut self, f: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T,
+        F: Send + 'static,
+        T: Send + 'static,
+    {
+        self.check()?;
+        let (th, handle) = create(Self::wrap(self.name.take(), f));
+        self.ready(th)?;
+        Ok(handle)
+    }
+
+    pub fn spawn_detached<F>(mut self, f: F) -> io::Result<()>
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        self.check()?;
+        let th = create_detached(Self::wrap(self.name.take(), f));
+        self.ready(th)?;
+        Ok(())
     }
 }
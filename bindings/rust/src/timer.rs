@@ -0,0 +1,205 @@
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::os::raw::c_ulong;
+use std::pin::Pin;
+use std::ptr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use super::*;
+use executor::Parker;
+
+pub(crate) fn duration_to_us(duration: Duration) -> u64 {
+    duration.as_secs() * 1000_000 + duration.subsec_nanos() as u64 / 1000
+}
+
+/// Converts an `Instant` into the runtime's clock (microseconds since start,
+/// as returned by `microtime`).
+fn instant_to_us(deadline: Instant) -> u64 {
+    let now = Instant::now();
+    let now_us = microtime();
+    now_us + duration_to_us(deadline.saturating_duration_since(now))
+}
+
+pub fn sleep_until(deadline: Instant) {
+    unsafe { ffi::timer_sleep_until(instant_to_us(deadline)) }
+}
+
+struct TimerData {
+    entry: UnsafeCell<ffi::timer_entry>,
+    f: UnsafeCell<Option<Box<dyn FnOnce() + Send + 'static>>>,
+}
+
+extern "C" fn timer_trampoline(arg: c_ulong) {
+    // Timer handlers run in the kthread's softirq context with preemption
+    // disabled, so hand the closure off to its own uthread.
+    let data = unsafe { Arc::from_raw(arg as *const TimerData) };
+    if let Some(f) = unsafe { (*data.f.get()).take() } {
+        thread::spawn_detached(move || f());
+    }
+}
+
+/// A one-shot timer that runs a closure on a new uthread once its deadline
+/// passes. Dropping the handle does not cancel the timer.
+pub struct Timer {
+    data: Arc<TimerData>,
+}
+impl Timer {
+    pub fn at<F>(deadline: Instant, f: F) -> Self
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        let data = Arc::new(TimerData {
+            entry: UnsafeCell::new(ffi::timer_entry {
+                armed: false,
+                idx: 0,
+                fn_: Some(timer_trampoline),
+                arg: 0,
+                localk: ptr::null_mut(),
+            }),
+            f: UnsafeCell::new(Some(Box::new(f))),
+        });
+
+        // The armed timer holds its own reference, released by whichever of
+        // the handler or a successful cancel runs.
+        let entry = data.entry.get();
+        unsafe {
+            (*entry).arg = Arc::into_raw(data.clone()) as c_ulong;
+            ffi::timer_start(entry, instant_to_us(deadline));
+        }
+        Timer { data }
+    }
+
+    pub fn after<F>(duration: Duration, f: F) -> Self
+    where
+        F: FnOnce(),
+        F: Send + 'static,
+    {
+        Self::at(Instant::now() + duration, f)
+    }
+
+    /// Cancels the timer. Returns true if the closure had not yet been
+    /// started, in which case it will never run.
+    pub fn cancel(&self) -> bool {
+        let entry = self.data.entry.get();
+        if unsafe { ffi::timer_cancel(entry) } {
+            unsafe { drop(Arc::from_raw((*entry).arg as *const TimerData)) };
+            true
+        } else {
+            false
+        }
+    }
+}
+unsafe impl Send for Timer {}
+unsafe impl Sync for Timer {}
+
+/// Wakes a parked uthread once a deadline passes.
+pub(crate) struct Timeout {
+    timer: Timer,
+    expired: Arc<AtomicBool>,
+}
+impl Timeout {
+    pub(crate) fn new(deadline: Instant, parker: &Arc<Parker>) -> Self {
+        let expired = Arc::new(AtomicBool::new(false));
+        let (e, p) = (expired.clone(), parker.clone());
+        let timer = Timer::at(deadline, move || {
+            e.store(true, Ordering::Release);
+            p.unpark();
+        });
+        Timeout {
+            timer: timer,
+            expired: expired,
+        }
+    }
+
+    pub(crate) fn expired(&self) -> bool {
+        self.expired.load(Ordering::Acquire)
+    }
+}
+impl Drop for Timeout {
+    fn drop(&mut self) {
+        self.timer.cancel();
+    }
+}
+
+/// Yields at a fixed period, without drifting when the caller is slow to
+/// call `tick`.
+pub struct Interval {
+    next: Instant,
+    period: Duration,
+}
+impl Interval {
+    pub fn new(period: Duration) -> Self {
+        Self::starting_at(Instant::now(), period)
+    }
+
+    pub fn starting_at(start: Instant, period: Duration) -> Self {
+        assert!(period > Duration::from_secs(0), "interval period must be non-zero");
+        Interval {
+            next: start,
+            period: period,
+        }
+    }
+
+    /// Sleeps until the next tick and returns its scheduled time. Ticks that
+    /// were missed entirely are skipped rather than delivered in a burst.
+    pub fn tick(&mut self) -> Instant {
+        let tick = self.next;
+        sleep_until(tick);
+
+        let now = Instant::now();
+        self.next = tick + self.period;
+        while self.next <= now {
+            self.next += self.period;
+        }
+        tick
+    }
+
+    pub fn period(&self) -> Duration {
+        self.period
+    }
+}
+
+/// A future that completes once its deadline has passed.
+pub struct Delay {
+    deadline: Instant,
+    timer: Option<Timer>,
+    signal: Arc<executor::Signal>,
+}
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+        if self.timer.is_none() {
+            let signal = self.signal.clone();
+            let deadline = self.deadline;
+            self.timer = Some(Timer::at(deadline, move || signal.notify()));
+        }
+        self.signal.poll_take(cx)
+    }
+}
+impl Drop for Delay {
+    fn drop(&mut self) {
+        if let Some(ref timer) = self.timer {
+            timer.cancel();
+        }
+    }
+}
+
+pub fn delay_until(deadline: Instant) -> Delay {
+    Delay {
+        deadline: deadline,
+        timer: None,
+        signal: Arc::new(executor::Signal::new()),
+    }
+}
+
+pub fn delay(duration: Duration) -> Delay {
+    delay_until(Instant::now() + duration)
+}
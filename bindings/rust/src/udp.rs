@@ -1,6 +1,12 @@
-use std::io::{self, Read, Write};
+use std::future::Future;
+use std::io::{self, IoSlice, Read, Write};
 use std::net::SocketAddrV4;
+use std::os::raw::c_ulong;
+use std::pin::Pin;
 use std::ptr;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use std::{panic, slice};
 
 use byteorder::{ByteOrder, NetworkEndian};
 
@@ -10,7 +16,7 @@ fn isize_to_result(i: isize) -> io::Result<usize> {
     if i >= 0 {
         Ok(i as usize)
     } else {
-        Err(io::Error::from_raw_os_error(i as i32))
+        Err(io_error(i as i32))
     }
 }
 
@@ -29,7 +35,7 @@ impl UdpConnection {
         let mut conn = ptr::null_mut();
         let ret = unsafe { ffi::udp_dial(laddr, raddr, &mut conn as *mut _) };
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            Err(io_error(ret as i32))
         } else {
             Ok(UdpConnection(conn))
         }
@@ -43,16 +49,20 @@ impl UdpConnection {
         let mut conn = ptr::null_mut();
         let ret = unsafe { ffi::udp_listen(laddr, &mut conn as *mut _) };
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            Err(io_error(ret as i32))
         } else {
             Ok(UdpConnection(conn))
         }
     }
 
-    pub fn set_buffers(&self, read_mbufs: u32, write_mbufs: u32) -> Result<(), i32> {
-        convert_error(unsafe {
-            ffi::udp_set_buffers(self.0, read_mbufs as c_int, write_mbufs as c_int)
-        })
+    pub fn set_buffers(&self, read_mbufs: u32, write_mbufs: u32) -> io::Result<()> {
+        let ret =
+            unsafe { ffi::udp_set_buffers(self.0, read_mbufs as c_int, write_mbufs as c_int) };
+        if ret < 0 {
+            Err(io_error(ret))
+        } else {
+            Ok(())
+        }
     }
 
     pub fn read_from(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddrV4)> {
@@ -83,6 +93,34 @@ impl UdpConnection {
         })
     }
 
+    fn read_from_nonblock(&self, buf: &mut [u8]) -> io::Result<(usize, SocketAddrV4)> {
+        let mut raddr = ffi::netaddr { ip: 0, port: 0 };
+        isize_to_result(unsafe {
+            ffi::udp_read_from_nonblock(
+                self.0,
+                buf.as_mut_ptr() as *mut c_void,
+                buf.len(),
+                &mut raddr as *mut _,
+            )
+        })
+        .map(|u| (u, SocketAddrV4::new(raddr.ip.into(), raddr.port)))
+    }
+
+    fn write_to_nonblock(&self, buf: &[u8], remote_addr: SocketAddrV4) -> io::Result<usize> {
+        let mut raddr = ffi::netaddr {
+            ip: NetworkEndian::read_u32(&remote_addr.ip().octets()),
+            port: remote_addr.port(),
+        };
+        isize_to_result(unsafe {
+            ffi::udp_write_to_nonblock(
+                self.0,
+                buf.as_ptr() as *const c_void as *mut c_void,
+                buf.len(),
+                &mut raddr as *mut _,
+            )
+        })
+    }
+
     /// Same as read, but doesn't take a &mut self.
     pub fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
         isize_to_result(unsafe {
@@ -104,6 +142,30 @@ impl UdpConnection {
         SocketAddrV4::new(remote_addr.ip.into(), remote_addr.port)
     }
 
+    /// Sets how long a read may block before failing with `TimedOut`. `None`
+    /// blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let us = timeout_to_us(timeout)?;
+        unsafe { ffi::udp_set_rx_timeout(self.0, us) };
+        Ok(())
+    }
+
+    /// Sets how long a write may block before failing with `TimedOut`. `None`
+    /// blocks indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        let us = timeout_to_us(timeout)?;
+        unsafe { ffi::udp_set_tx_timeout(self.0, us) };
+        Ok(())
+    }
+
+    pub fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(us_to_timeout(unsafe { ffi::udp_get_rx_timeout(self.0) }))
+    }
+
+    pub fn write_timeout(&self) -> io::Result<Option<Duration>> {
+        Ok(us_to_timeout(unsafe { ffi::udp_get_tx_timeout(self.0) }))
+    }
+
     pub fn shutdown(&self) {
         unsafe { ffi::udp_shutdown(self.0) }
     }
@@ -145,6 +207,44 @@ impl<'a> Write for &'a UdpConnection {
     }
 }
 
+unsafe impl poll::Pollable for UdpConnection {
+    unsafe fn poll_arm(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()> {
+        let ret = ffi::udp_poll_arm(self.0, waiter, trigger, token as c_ulong);
+        if ret < 0 {
+            Err(io_error(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn poll_disarm(&self, trigger: *mut ffi::poll_trigger_t) {
+        ffi::udp_poll_disarm(self.0, trigger)
+    }
+}
+unsafe impl executor::PollableTx for UdpConnection {
+    unsafe fn poll_arm_tx(
+        &self,
+        waiter: *mut ffi::poll_waiter_t,
+        trigger: *mut ffi::poll_trigger_t,
+        token: usize,
+    ) -> io::Result<()> {
+        let ret = ffi::udp_poll_arm_tx(self.0, waiter, trigger, token as c_ulong);
+        if ret < 0 {
+            Err(io_error(ret))
+        } else {
+            Ok(())
+        }
+    }
+
+    unsafe fn poll_disarm_tx(&self, trigger: *mut ffi::poll_trigger_t) {
+        ffi::udp_poll_disarm_tx(self.0, trigger)
+    }
+}
 impl Drop for UdpConnection {
     fn drop(&mut self) {
         unsafe { ffi::udp_close(self.0) }
@@ -154,44 +254,188 @@ impl Drop for UdpConnection {
 unsafe impl Send for UdpConnection {}
 unsafe impl Sync for UdpConnection {}
 
+/// A `UdpConnection` whose reads and writes are awaitable. An empty ingress
+/// queue or a full egress queue leaves the future pending instead of parking
+/// the uthread running the task.
+pub struct AsyncUdpConnection {
+    conn: UdpConnection,
+    io: executor::IoSource,
+}
+impl AsyncUdpConnection {
+    pub fn new(conn: UdpConnection) -> io::Result<Self> {
+        let io = executor::IoSource::register(&conn)?;
+        Ok(AsyncUdpConnection { conn: conn, io: io })
+    }
+
+    pub fn dial(local_addr: SocketAddrV4, remote_addr: SocketAddrV4) -> io::Result<Self> {
+        Self::new(UdpConnection::dial(local_addr, remote_addr)?)
+    }
+
+    pub fn listen(local_addr: SocketAddrV4) -> io::Result<Self> {
+        Self::new(UdpConnection::listen(local_addr)?)
+    }
+
+    pub fn read_from<'a>(&'a self, buf: &'a mut [u8]) -> UdpReadFuture<'a> {
+        UdpReadFuture { conn: self, buf: buf }
+    }
+
+    pub fn write_to<'a>(&'a self, buf: &'a [u8], remote_addr: SocketAddrV4) -> UdpWriteFuture<'a> {
+        UdpWriteFuture {
+            conn: self,
+            buf: buf,
+            remote_addr: remote_addr,
+        }
+    }
+
+    pub fn get_ref(&self) -> &UdpConnection {
+        &self.conn
+    }
+}
+impl Drop for AsyncUdpConnection {
+    fn drop(&mut self) {
+        unsafe { self.io.deregister(&self.conn) }
+    }
+}
+
+pub struct UdpReadFuture<'a> {
+    conn: &'a AsyncUdpConnection,
+    buf: &'a mut [u8],
+}
+impl<'a> Future for UdpReadFuture<'a> {
+    type Output = io::Result<(usize, SocketAddrV4)>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = &mut *self;
+        let conn = this.conn;
+        executor::poll_io(|| conn.conn.read_from_nonblock(this.buf), || conn.io.poll_readable(cx))
+    }
+}
+
+pub struct UdpWriteFuture<'a> {
+    conn: &'a AsyncUdpConnection,
+    buf: &'a [u8],
+    remote_addr: SocketAddrV4,
+}
+impl<'a> Future for UdpWriteFuture<'a> {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let conn = self.conn;
+        executor::poll_io(
+            || conn.conn.write_to_nonblock(self.buf, self.remote_addr),
+            || conn.io.poll_writable(cx),
+        )
+    }
+}
+
+extern "C" fn spawner_trampoline<F>(d: *mut ffi::udp_spawn_data)
+where
+    F: Fn(Request),
+    F: Send + Sync + 'static,
+{
+    let f = unsafe { &*((*d).arg as *const F) };
+    let req = Request { data: unsafe { *d } };
+    if let Err(payload) = panic::catch_unwind(panic::AssertUnwindSafe(move || f(req))) {
+        thread::report_panic(payload);
+    }
+    thread::run_local_dtors();
+}
+
+extern "C" fn spawner_release<F>(arg: *mut c_void) {
+    drop(unsafe { Box::from_raw(arg as *mut F) });
+}
+
+/// Runs a handler on a fresh uthread for every datagram that arrives on a
+/// local address.
 pub struct UdpSpawner(*mut ffi::udpspawner_t);
 impl UdpSpawner {
-    pub unsafe fn new(
-        local_addr: SocketAddrV4,
-        f: extern "C" fn(*mut ffi::udp_spawn_data),
-    ) -> io::Result<Self> {
-        println!("{:?}", local_addr);
+    pub fn new<F>(local_addr: SocketAddrV4, f: F) -> io::Result<Self>
+    where
+        F: Fn(Request),
+        F: Send + Sync + 'static,
+    {
         let laddr = ffi::netaddr {
             ip: NetworkEndian::read_u32(&local_addr.ip().octets()),
             port: local_addr.port(),
         };
 
+        // The runtime releases the handler once the spawner is destroyed and
+        // every in-flight request has been handled.
+        let arg = Box::into_raw(Box::new(f));
         let mut spawner: *mut ffi::udpspawner_t = ptr::null_mut();
-        let ret = ffi::udp_create_spawner(laddr, Some(f), &mut spawner as *mut *mut _);
+        let ret = unsafe {
+            ffi::udp_create_spawner_arg(
+                laddr,
+                Some(spawner_trampoline::<F>),
+                arg as *mut c_void,
+                Some(spawner_release::<F>),
+                &mut spawner as *mut *mut _,
+            )
+        };
 
         if ret < 0 {
-            Err(io::Error::from_raw_os_error(ret as i32))
+            drop(unsafe { Box::from_raw(arg) });
+            Err(io_error(ret as i32))
         } else {
             Ok(UdpSpawner(spawner))
         }
     }
+}
+impl Drop for UdpSpawner {
+    fn drop(&mut self) {
+        unsafe { ffi::udp_destroy_spawner(self.0) }
+    }
+}
+unsafe impl Send for UdpSpawner {}
+unsafe impl Sync for UdpSpawner {}
+
+/// A datagram delivered to a `UdpSpawner` handler. Its buffer is returned to
+/// the runtime when the request is dropped.
+pub struct Request {
+    data: ffi::udp_spawn_data,
+}
+impl Request {
+    pub fn payload(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.data.buf as *const u8, self.data.len) }
+    }
+
+    pub fn local_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.data.laddr.ip.into(), self.data.laddr.port)
+    }
 
-    pub unsafe fn reply(d: *mut ffi::udp_spawn_data, buf: &[u8]) -> io::Result<usize> {
-        isize_to_result(ffi::udp_send(
-            buf.as_ptr() as *const c_void,
-            buf.len(),
-            (*d).laddr,
-            (*d).raddr,
-        ))
+    pub fn remote_addr(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.data.raddr.ip.into(), self.data.raddr.port)
+    }
+
+    /// Sends `buf` back to the request's sender.
+    pub fn respond(&self, buf: &[u8]) -> io::Result<usize> {
+        isize_to_result(unsafe {
+            ffi::udp_send(
+                buf.as_ptr() as *const c_void,
+                buf.len(),
+                self.data.laddr,
+                self.data.raddr,
+            )
+        })
     }
 
-    pub unsafe fn release_data(d: *mut ffi::udp_spawn_data) {
-        ffi::udp_spawn_data_release((*d).release_data)
+    /// Gathers `bufs` into a single response datagram.
+    pub fn respond_vectored(&self, bufs: &[IoSlice]) -> io::Result<usize> {
+        let iovcnt = cmp::min(bufs.len(), c_int::max_value() as usize) as c_int;
+        isize_to_result(unsafe {
+            ffi::udp_sendv(
+                bufs.as_ptr() as *const ffi::iovec,
+                iovcnt,
+                self.data.laddr,
+                self.data.raddr,
+            )
+        })
     }
 }
-impl Drop for UdpSpawner {
+impl Drop for Request {
     fn drop(&mut self) {
-        println!("!!!!!!!!!!!!");
-        unsafe { ffi::udp_destroy_spawner(self.0) }
+        unsafe { ffi::udp_spawn_data_release(self.data.release_data) }
     }
 }
+unsafe impl Send for Request {}
+unsafe impl Sync for Request {}
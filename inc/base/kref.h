@@ -46,6 +46,28 @@ kref_get(struct kref *ref)
 	atomic_inc(&ref->cnt);
 }
 
+/**
+ * kref_get_unless_zero - increments the reference count unless it is zero
+ * @ref: the kref
+ *
+ * Useful for lookups (e.g. under RCU) that can race with the final put.
+ *
+ * Returns true if a reference was taken.
+ */
+static inline bool
+kref_get_unless_zero(struct kref *ref)
+{
+	int cnt;
+
+	do {
+		cnt = atomic_read(&ref->cnt);
+		if (cnt == 0)
+			return false;
+	} while (!atomic_cmpxchg(&ref->cnt, cnt, cnt + 1));
+
+	return true;
+}
+
 /**
  * kref_put - atomically decrements the reference count, releasing the object
  *	      when it reaches zero
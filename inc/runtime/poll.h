@@ -25,6 +25,11 @@ typedef struct poll_trigger {
 
 /*
  * Waiter API
+ *
+ * A trigger is delivered at most once until it is consumed: poll_wait()
+ * returns its data and re-enables it, so the same trigger can fire again
+ * (e.g. a connection that still has data queued). poll_arm() also resets it,
+ * so a disarmed trigger may be armed again.
  */
 
 extern void poll_init(poll_waiter_t *w);
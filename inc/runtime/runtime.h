@@ -21,6 +21,7 @@ extern int runtime_init(const char *cfgpath, thread_fn_t main_fn, void *arg);
 extern struct congestion_info *runtime_congestion;
 
 extern unsigned int maxks;
+extern unsigned int nrks;
 extern unsigned int guaranteedks;
 extern atomic_t runningks;
 
@@ -62,6 +63,16 @@ static inline int runtime_max_cores(void)
 	return maxks;
 }
 
+/**
+ * runtime_kthreads - returns the number of kthreads the runtime attached
+ *
+ * Valid kthread indices (e.g. for thread_ready_on()) are below this number.
+ */
+static inline int runtime_kthreads(void)
+{
+	return nrks;
+}
+
 /**
  * runtime_guaranteed_cores - returns the guaranteed number of cores
  *
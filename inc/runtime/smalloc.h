@@ -13,6 +13,10 @@
 extern void *smalloc(size_t size) __smalloc_attr;
 extern void *__szalloc(size_t size) __smalloc_attr;
 extern void sfree(void *item);
+extern bool smalloc_owns(void *item);
+
+/* true once the calling kthread can use smalloc() and sfree() */
+extern __thread bool smalloc_thread_ready;
 
 /**
  * szalloc - allocates zeroed memory
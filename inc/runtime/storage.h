@@ -9,6 +9,14 @@
 extern int storage_write(const void *payload, uint64_t lba, uint32_t lba_count);
 extern int storage_read(void *dest, uint64_t lba, uint32_t lba_count);
 
+typedef void (*storage_cb_fn_t)(void *arg, int status);
+
+extern int storage_write_async(const void *payload, uint64_t lba,
+			       uint32_t lba_count, storage_cb_fn_t cb,
+			       void *arg);
+extern int storage_read_async(void *dest, uint64_t lba, uint32_t lba_count,
+			      storage_cb_fn_t cb, void *arg);
+
 
 
 /*
@@ -28,3 +36,13 @@ static inline uint64_t storage_num_blocks(void)
 	extern uint64_t num_blocks;
 	return num_blocks;
 }
+
+/*
+ * storage_max_xfer_blocks - gets the most blocks a single asynchronous
+ * request may transfer
+ */
+static inline uint32_t storage_max_xfer_blocks(void)
+{
+	extern uint32_t max_xfer_blocks;
+	return max_xfer_blocks;
+}
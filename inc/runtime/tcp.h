@@ -5,6 +5,7 @@
 #pragma once
 
 #include <runtime/net.h>
+#include <runtime/poll.h>
 #include <sys/uio.h>
 #include <sys/socket.h>
 
@@ -25,8 +26,20 @@ extern void tcp_qshutdown(tcpqueue_t *q);
 extern void tcp_qclose(tcpqueue_t *q);
 extern struct netaddr tcp_local_addr(tcpconn_t *c);
 extern struct netaddr tcp_remote_addr(tcpconn_t *c);
+extern void tcp_set_rx_timeout(tcpconn_t *c, uint64_t timeout_us);
+extern void tcp_set_tx_timeout(tcpconn_t *c, uint64_t timeout_us);
+extern uint64_t tcp_get_rx_timeout(tcpconn_t *c);
+extern uint64_t tcp_get_tx_timeout(tcpconn_t *c);
+extern int tcp_poll_arm(tcpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+			unsigned long data);
+extern void tcp_poll_disarm(tcpconn_t *c, poll_trigger_t *t);
+extern int tcp_poll_arm_tx(tcpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+			   unsigned long data);
+extern void tcp_poll_disarm_tx(tcpconn_t *c, poll_trigger_t *t);
 extern ssize_t tcp_read(tcpconn_t *c, void *buf, size_t len);
 extern ssize_t tcp_write(tcpconn_t *c, const void *buf, size_t len);
+extern ssize_t tcp_read_nonblock(tcpconn_t *c, void *buf, size_t len);
+extern ssize_t tcp_write_nonblock(tcpconn_t *c, const void *buf, size_t len);
 extern ssize_t tcp_readv(tcpconn_t *c, const struct iovec *iov, int iovcnt);
 extern ssize_t tcp_writev(tcpconn_t *c, const struct iovec *iov, int iovcnt);
 extern int tcp_shutdown(tcpconn_t *c, int how);
@@ -23,6 +23,7 @@ extern void thread_park_and_unlock_np(spinlock_t *l);
 extern void thread_park_and_preempt_enable(void);
 extern void thread_ready(thread_t *thread);
 extern void thread_ready_head(thread_t *thread);
+extern int thread_ready_on(thread_t *thread, unsigned int kidx);
 extern thread_t *thread_create(thread_fn_t fn, void *arg);
 extern thread_t *thread_create_with_buf(thread_fn_t fn, void **buf, size_t len);
 
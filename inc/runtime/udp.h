@@ -7,6 +7,7 @@
 #include <base/types.h>
 #include <net/udp.h>
 #include <runtime/net.h>
+#include <runtime/poll.h>
 #include <sys/uio.h>
 
 /* the maximum size of a UDP payload */
@@ -26,10 +27,24 @@ extern int udp_listen(struct netaddr laddr, udpconn_t **c_out);
 extern struct netaddr udp_local_addr(udpconn_t *c);
 extern struct netaddr udp_remote_addr(udpconn_t *c);
 extern int udp_set_buffers(udpconn_t *c, int read_mbufs, int write_mbufs);
+extern void udp_set_rx_timeout(udpconn_t *c, uint64_t timeout_us);
+extern void udp_set_tx_timeout(udpconn_t *c, uint64_t timeout_us);
+extern uint64_t udp_get_rx_timeout(udpconn_t *c);
+extern uint64_t udp_get_tx_timeout(udpconn_t *c);
+extern int udp_poll_arm(udpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+			unsigned long data);
+extern void udp_poll_disarm(udpconn_t *c, poll_trigger_t *t);
+extern int udp_poll_arm_tx(udpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+			   unsigned long data);
+extern void udp_poll_disarm_tx(udpconn_t *c, poll_trigger_t *t);
 extern ssize_t udp_read_from(udpconn_t *c, void *buf, size_t len,
 			     struct netaddr *raddr);
 extern ssize_t udp_write_to(udpconn_t *c, const void *buf, size_t len,
 			    const struct netaddr *raddr);
+extern ssize_t udp_read_from_nonblock(udpconn_t *c, void *buf, size_t len,
+				      struct netaddr *raddr);
+extern ssize_t udp_write_to_nonblock(udpconn_t *c, const void *buf, size_t len,
+				     const struct netaddr *raddr);
 extern ssize_t udp_read(udpconn_t *c, void *buf, size_t len);
 extern ssize_t udp_write(udpconn_t *c, const void *buf, size_t len);
 extern void udp_shutdown(udpconn_t *c);
@@ -49,12 +64,17 @@ struct udp_spawn_data {
 	struct netaddr	laddr;
 	struct netaddr	raddr;
 	void		*release_data;
+	void		*arg;
 };
 
 typedef void (*udpspawn_fn_t)(struct udp_spawn_data *d);
+typedef void (*udpspawn_release_fn_t)(void *arg);
 
 extern int udp_create_spawner(struct netaddr laddr, udpspawn_fn_t fn,
 			      udpspawner_t **s_out);
+extern int udp_create_spawner_arg(struct netaddr laddr, udpspawn_fn_t fn,
+				  void *arg, udpspawn_release_fn_t release,
+				  udpspawner_t **s_out);
 extern void udp_destroy_spawner(udpspawner_t *s);
 extern ssize_t udp_send(const void *buf, size_t len,
 			struct netaddr laddr, struct netaddr raddr);
@@ -76,6 +96,14 @@ static inline ssize_t udp_respond(const void *buf, size_t len,
 	return udp_send(buf, len, d->laddr, d->raddr);
 }
 
+/**
+ * udp_respondv - sends a vectored response datagram to a spawner datagram
+ * @iov: a pointer to the IO vector
+ * @iovcnt: the number of vectors in @iov
+ * @d: the UDP spawner data
+ *
+ * Returns the number of payload bytes sent, otherwise fail.
+ */
 static inline ssize_t udp_respondv(const struct iovec *iov, int iovcnt,
 				   struct udp_spawn_data *d)
 {
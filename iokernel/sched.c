@@ -323,7 +323,8 @@ sched_measure_kthread_delay(struct thread *th,
 	last_tail = th->last_rq_tail;
 	cur_tail = load_acquire(&th->q_ptrs->rq_tail);
 	last_head = th->last_rq_head;
-	cur_head = ACCESS_ONCE(th->q_ptrs->rq_head);
+	cur_head = ACCESS_ONCE(th->q_ptrs->rq_head) +
+		   ACCESS_ONCE(th->q_ptrs->rq_remote);
 	th->last_rq_head = cur_head;
 	th->last_rq_tail = cur_tail;
 
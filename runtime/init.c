@@ -13,6 +13,7 @@
 #include "defs.h"
 
 static pthread_barrier_t init_barrier;
+static int init_thread_ret;
 
 struct init_entry {
 	const char *name;
@@ -125,10 +126,17 @@ static void *pthread_entry(void *data)
 {
 	int ret;
 
+	/*
+	 * a failure is reported by runtime_init() once every kthread is done,
+	 * and then no kthread starts scheduling
+	 */
 	ret = runtime_init_thread();
-	BUG_ON(ret);
+	if (ret)
+		ACCESS_ONCE(init_thread_ret) = ret;
 
 	pthread_barrier_wait(&init_barrier);
+	if (ACCESS_ONCE(init_thread_ret))
+		return NULL;
 	sched_start();
 
 	/* never reached unless things are broken */
@@ -191,7 +199,8 @@ int runtime_init(const char *cfgpath, thread_fn_t main_fn, void *arg)
 	}
 
 	ret = runtime_init_thread();
-	BUG_ON(ret);
+	if (ret)
+		return ret;
 
 	log_info("spawning %d kthreads", maxks);
 	for (i = 1; i < maxks; i++) {
@@ -201,21 +210,23 @@ int runtime_init(const char *cfgpath, thread_fn_t main_fn, void *arg)
 
 	pthread_barrier_wait(&init_barrier);
 
+	ret = ACCESS_ONCE(init_thread_ret);
+	if (ret) {
+		log_err("per-thread init failed, ret = %d", ret);
+		return ret;
+	}
+
 	ret = ioqueues_register_iokernel();
 	if (ret) {
 		log_err("couldn't register with iokernel, ret = %d", ret);
 		return ret;
 	}
 
-	/* point of no return starts here */
-
-	ret = thread_spawn_main(main_fn, arg);
-	BUG_ON(ret);
-
 	ret = run_init_handlers("late", late_init_handlers,
 				ARRAY_SIZE(late_init_handlers));
 	BUG_ON(ret);
 
+	/* runs before @main_fn becomes runnable, so it can still fail startup */
 	if (late_init_hook) {
 		ret = late_init_hook();
 		if (ret) {
@@ -224,6 +235,11 @@ int runtime_init(const char *cfgpath, thread_fn_t main_fn, void *arg)
 		}
 	}
 
+	/* point of no return starts here */
+
+	ret = thread_spawn_main(main_fn, arg);
+	BUG_ON(ret);
+
 	sched_start();
 
 	/* never reached unless things are broken */
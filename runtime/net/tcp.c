@@ -173,6 +173,7 @@ void tcp_conn_set_state(tcpconn_t *c, int new_state)
 
 	tcp_debug_state_change(c, c->pcb.state, new_state);
 	c->pcb.state = new_state;
+	tcp_conn_poll_tx(c);
 	tcp_timer_update(c);
 }
 
@@ -232,6 +233,7 @@ tcpconn_t *tcp_conn_alloc(void)
 	c->rx_closed = false;
 	c->rx_exclusive = false;
 	waitq_init(&c->rx_wq);
+	c->rx_trig = NULL;
 	c->rxq_ooo_len = 0;
 	list_head_init(&c->rxq_ooo);
 	list_head_init(&c->rxq);
@@ -240,6 +242,7 @@ tcpconn_t *tcp_conn_alloc(void)
 	c->tx_closed = false;
 	c->tx_exclusive = false;
 	waitq_init(&c->tx_wq);
+	c->tx_trig = NULL;
 	c->tx_last_ack = 0;
 	c->tx_last_win = 0;
 	c->tx_pending = NULL;
@@ -247,6 +250,8 @@ tcpconn_t *tcp_conn_alloc(void)
 	c->do_fast_retransmit = false;
 
 	/* timeouts */
+	c->rx_timeout = 0;
+	c->tx_timeout = 0;
 	c->next_timeout = -1L;
 	c->ack_delayed = false;
 	c->ack_ts = 0;
@@ -634,7 +639,7 @@ int tcp_dial_conn_affinity(tcpconn_t *in, struct netaddr raddr, tcpconn_t **c_ou
 
 /**
  * tcp_dial_affinity - opens a TCP connection with specific kthread affinity
- * @in: the connection to match to
+ * @in_aff: the kthread index that should handle the connection
  * @raddr: the remote address
  * @c_out: a pointer to store the new connection
  *
@@ -651,6 +656,9 @@ int tcp_dial_affinity(uint32_t in_aff, struct netaddr raddr, tcpconn_t **c_out)
 	struct netaddr laddr = {0};
 	tcpconn_t *c;
 
+	if (in_aff >= maxks)
+		return -EINVAL;
+
 	base_port = start_port = rand_crc32c(in_aff);
 
 	while (true) {
@@ -689,23 +697,172 @@ struct netaddr tcp_remote_addr(tcpconn_t *c)
 	return c->e.raddr;
 }
 
-static ssize_t tcp_read_wait(tcpconn_t *c, size_t len,
+/**
+ * tcp_poll_arm - fires a poll trigger whenever a TCP connection is readable
+ * @c: the TCP connection
+ * @w: the waiter to notify
+ * @t: the trigger to attach
+ * @data: data to provide to poll_wait() when the trigger fires
+ *
+ * A connection is readable when it has data queued or its ingress is closed.
+ * The trigger fires immediately if that is already the case, and fires again
+ * after each read that leaves the connection readable. Only one trigger can
+ * be attached at a time.
+ *
+ * Returns 0 if successful, or -EBUSY if a trigger is already attached.
+ */
+int tcp_poll_arm(tcpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+		 unsigned long data)
+{
+	spin_lock_np(&c->lock);
+	if (c->rx_trig) {
+		spin_unlock_np(&c->lock);
+		return -EBUSY;
+	}
+
+	poll_arm(w, t, data);
+	c->rx_trig = t;
+	tcp_conn_poll_rx(c);
+	spin_unlock_np(&c->lock);
+	return 0;
+}
+
+/**
+ * tcp_poll_disarm - detaches a poll trigger from a TCP connection
+ * @c: the TCP connection
+ * @t: the trigger, previously attached with tcp_poll_arm()
+ */
+void tcp_poll_disarm(tcpconn_t *c, poll_trigger_t *t)
+{
+	spin_lock_np(&c->lock);
+	BUG_ON(c->rx_trig != t);
+	c->rx_trig = NULL;
+	spin_unlock_np(&c->lock);
+
+	poll_disarm(t);
+}
+
+/**
+ * tcp_poll_arm_tx - fires a poll trigger whenever a TCP connection is writable
+ * @c: the TCP connection
+ * @w: the waiter to notify
+ * @t: the trigger to attach
+ * @data: data to provide to poll_wait() when the trigger fires
+ *
+ * A connection is writable when tcp_write() would not block: it is
+ * established and has send window available, or its egress is closed. The
+ * trigger fires immediately if that is already the case, and fires again
+ * whenever the connection becomes writable. Only one trigger can be attached
+ * at a time.
+ *
+ * Returns 0 if successful, or -EBUSY if a trigger is already attached.
+ */
+int tcp_poll_arm_tx(tcpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+		    unsigned long data)
+{
+	spin_lock_np(&c->lock);
+	if (c->tx_trig) {
+		spin_unlock_np(&c->lock);
+		return -EBUSY;
+	}
+
+	poll_arm(w, t, data);
+	c->tx_trig = t;
+	tcp_conn_poll_tx(c);
+	spin_unlock_np(&c->lock);
+	return 0;
+}
+
+/**
+ * tcp_poll_disarm_tx - detaches a write poll trigger from a TCP connection
+ * @c: the TCP connection
+ * @t: the trigger, previously attached with tcp_poll_arm_tx()
+ */
+void tcp_poll_disarm_tx(tcpconn_t *c, poll_trigger_t *t)
+{
+	spin_lock_np(&c->lock);
+	BUG_ON(c->tx_trig != t);
+	c->tx_trig = NULL;
+	spin_unlock_np(&c->lock);
+
+	poll_disarm(t);
+}
+
+/**
+ * tcp_set_rx_timeout - bounds how long a read may block
+ * @c: the TCP connection
+ * @timeout_us: the timeout in microseconds, or 0 to block indefinitely
+ *
+ * A read that times out returns -ETIMEDOUT and leaves the connection intact.
+ */
+void tcp_set_rx_timeout(tcpconn_t *c, uint64_t timeout_us)
+{
+	spin_lock_np(&c->lock);
+	c->rx_timeout = timeout_us;
+	spin_unlock_np(&c->lock);
+}
+
+/**
+ * tcp_set_tx_timeout - bounds how long a write may block
+ * @c: the TCP connection
+ * @timeout_us: the timeout in microseconds, or 0 to block indefinitely
+ *
+ * A write that times out returns -ETIMEDOUT and leaves the connection intact.
+ */
+void tcp_set_tx_timeout(tcpconn_t *c, uint64_t timeout_us)
+{
+	spin_lock_np(&c->lock);
+	c->tx_timeout = timeout_us;
+	spin_unlock_np(&c->lock);
+}
+
+/**
+ * tcp_get_rx_timeout - gets the read timeout in microseconds (0 if none)
+ * @c: the TCP connection
+ */
+uint64_t tcp_get_rx_timeout(tcpconn_t *c)
+{
+	return ACCESS_ONCE(c->rx_timeout);
+}
+
+/**
+ * tcp_get_tx_timeout - gets the write timeout in microseconds (0 if none)
+ * @c: the TCP connection
+ */
+uint64_t tcp_get_tx_timeout(tcpconn_t *c)
+{
+	return ACCESS_ONCE(c->tx_timeout);
+}
+
+static ssize_t tcp_read_wait(tcpconn_t *c, size_t len, bool nonblock,
 			     struct list_head *q, struct mbuf **mout)
 {
+	struct waitq_timeout t;
 	struct mbuf *m;
 	size_t readlen = 0;
 	bool do_ack = false;
 
 	*mout = NULL;
 	spin_lock_np(&c->lock);
+	waitq_timeout_init(&t, &c->rx_wq, &c->lock, c->rx_timeout);
 
 	/* block until there is an actionable event */
-	while (!c->rx_closed && (c->rx_exclusive || list_empty(&c->rxq)))
-		waitq_wait(&c->rx_wq, &c->lock);
+	while (!c->rx_closed && (c->rx_exclusive || list_empty(&c->rxq))) {
+		if (nonblock) {
+			spin_unlock_np(&c->lock);
+			return -EAGAIN;
+		}
+		if (!waitq_wait_timeout(&t)) {
+			spin_unlock_np(&c->lock);
+			waitq_timeout_finish(&t);
+			return -ETIMEDOUT;
+		}
+	}
 
 	/* is the socket closed? */
 	if (c->rx_closed) {
 		spin_unlock_np(&c->lock);
+		waitq_timeout_finish(&t);
 		return -c->err;
 	}
 
@@ -737,6 +894,7 @@ static ssize_t tcp_read_wait(tcpconn_t *c, size_t len,
 	if (c->pcb.rcv_wnd >= c->tx_last_win + c->winmax / 4)
 		do_ack = true;
 	spin_unlock_np(&c->lock);
+	waitq_timeout_finish(&t);
 
 	if (do_ack)
 		tcp_tx_ack(c);
@@ -754,20 +912,12 @@ static void tcp_read_finish(tcpconn_t *c, struct mbuf *m)
 	spin_lock_np(&c->lock);
 	c->rx_exclusive = false;
 	waitq_release_start(&c->rx_wq, &waiters);
+	tcp_conn_poll_rx(c);
 	spin_unlock_np(&c->lock);
 	waitq_release_finish(&waiters);
 }
 
-/**
- * tcp_read - reads data from a TCP connection
- * @c: the TCP connection
- * @buf: a buffer to store the read data
- * @len: the length of @buf
- *
- * Returns the number of bytes read, 0 if the connection is closed, or < 0
- * if an error occurred.
- */
-ssize_t tcp_read(tcpconn_t *c, void *buf, size_t len)
+static ssize_t __tcp_read(tcpconn_t *c, void *buf, size_t len, bool nonblock)
 {
 	char *pos = buf;
 	struct list_head q;
@@ -777,7 +927,7 @@ ssize_t tcp_read(tcpconn_t *c, void *buf, size_t len)
 	list_head_init(&q);
 
 	/* wait for data to become available */
-	ret = tcp_read_wait(c, len, &q, &m);
+	ret = tcp_read_wait(c, len, nonblock, &q, &m);
 
 	/* check if connection was closed */
 	if (ret <= 0)
@@ -807,6 +957,33 @@ ssize_t tcp_read(tcpconn_t *c, void *buf, size_t len)
 	return ret;
 }
 
+/**
+ * tcp_read - reads data from a TCP connection
+ * @c: the TCP connection
+ * @buf: a buffer to store the read data
+ * @len: the length of @buf
+ *
+ * Returns the number of bytes read, 0 if the connection is closed, or < 0
+ * if an error occurred.
+ */
+ssize_t tcp_read(tcpconn_t *c, void *buf, size_t len)
+{
+	return __tcp_read(c, buf, len, false);
+}
+
+/**
+ * tcp_read_nonblock - reads data from a TCP connection without blocking
+ * @c: the TCP connection
+ * @buf: a buffer to store the read data
+ * @len: the length of @buf
+ *
+ * Like tcp_read(), but returns -EAGAIN instead of waiting for data.
+ */
+ssize_t tcp_read_nonblock(tcpconn_t *c, void *buf, size_t len)
+{
+	return __tcp_read(c, buf, len, true);
+}
+
 static size_t iov_len(const struct iovec *iov, int iovcnt)
 {
 	size_t len = 0;
@@ -838,7 +1015,7 @@ ssize_t tcp_readv(tcpconn_t *c, const struct iovec *iov, int iovcnt)
 	list_head_init(&q);
 
 	/* wait for data to become available */
-	len = tcp_read_wait(c, len, &q, &m);
+	len = tcp_read_wait(c, len, false, &q, &m);
 
 	/* check if connection was closed */
 	if (len <= 0)
@@ -895,20 +1072,30 @@ ssize_t tcp_readv(tcpconn_t *c, const struct iovec *iov, int iovcnt)
 	return len;
 }
 
-static int tcp_write_wait(tcpconn_t *c, size_t *winlen)
+static int tcp_write_wait(tcpconn_t *c, bool nonblock, size_t *winlen)
 {
+	struct waitq_timeout t;
+
 	spin_lock_np(&c->lock);
+	waitq_timeout_init(&t, &c->tx_wq, &c->lock, c->tx_timeout);
 
 	/* block until there is an actionable event */
-	while (!c->tx_closed &&
-	       (c->pcb.state < TCP_STATE_ESTABLISHED || c->tx_exclusive ||
-		wraps_lte(c->pcb.snd_una + c->pcb.snd_wnd + 1, c->pcb.snd_nxt))) {
-		waitq_wait(&c->tx_wq, &c->lock);
+	while (!tcp_conn_writable(c)) {
+		if (nonblock) {
+			spin_unlock_np(&c->lock);
+			return -EAGAIN;
+		}
+		if (!waitq_wait_timeout(&t)) {
+			spin_unlock_np(&c->lock);
+			waitq_timeout_finish(&t);
+			return -ETIMEDOUT;
+		}
 	}
 
 	/* is the socket closed? */
 	if (c->tx_closed) {
 		spin_unlock_np(&c->lock);
+		waitq_timeout_finish(&t);
 		return c->err ? -c->err : -EPIPE;
 	}
 
@@ -920,6 +1107,7 @@ static int tcp_write_wait(tcpconn_t *c, size_t *winlen)
 	c->acks_delayed_cnt = 0;
 	c->ack_delayed = false;
 	spin_unlock_np(&c->lock);
+	waitq_timeout_finish(&t);
 
 	return 0;
 }
@@ -955,6 +1143,7 @@ static void tcp_write_finish(tcpconn_t *c)
 
 	tcp_timer_update(c);
 	waitq_release_start(&c->tx_wq, &waiters);
+	tcp_conn_poll_tx(c);
 	spin_unlock_np(&c->lock);
 
 	tcp_tx_fast_retransmit_finish(c, retransmit);
@@ -962,22 +1151,14 @@ static void tcp_write_finish(tcpconn_t *c)
 	mbuf_list_free(&q);
 }
 
-/**
- * tcp_write - writes data to a TCP connection
- * @c: the TCP connection
- * @buf: a buffer from which to copy the data
- * @len: the length of the data
- *
- * Returns the number of bytes written (could be less than @len), or < 0
- * if there was a failure.
- */
-ssize_t tcp_write(tcpconn_t *c, const void *buf, size_t len)
+static ssize_t __tcp_write(tcpconn_t *c, const void *buf, size_t len,
+			   bool nonblock)
 {
 	size_t winlen;
 	ssize_t ret;
 
 	/* block until the data can be sent */
-	ret = tcp_write_wait(c, &winlen);
+	ret = tcp_write_wait(c, nonblock, &winlen);
 	if (ret)
 		return ret;
 
@@ -990,6 +1171,33 @@ ssize_t tcp_write(tcpconn_t *c, const void *buf, size_t len)
 	return ret;
 }
 
+/**
+ * tcp_write - writes data to a TCP connection
+ * @c: the TCP connection
+ * @buf: a buffer from which to copy the data
+ * @len: the length of the data
+ *
+ * Returns the number of bytes written (could be less than @len), or < 0
+ * if there was a failure.
+ */
+ssize_t tcp_write(tcpconn_t *c, const void *buf, size_t len)
+{
+	return __tcp_write(c, buf, len, false);
+}
+
+/**
+ * tcp_write_nonblock - writes data to a TCP connection without blocking
+ * @c: the TCP connection
+ * @buf: a buffer from which to copy the data
+ * @len: the length of the data
+ *
+ * Like tcp_write(), but returns -EAGAIN instead of waiting for send window.
+ */
+ssize_t tcp_write_nonblock(tcpconn_t *c, const void *buf, size_t len)
+{
+	return __tcp_write(c, buf, len, true);
+}
+
 /**
  * tcp_writev - writes vectored data to a TCP connection
  * @c: the TCP connection
@@ -1003,10 +1211,16 @@ ssize_t tcp_writev(tcpconn_t *c, const struct iovec *iov, int iovcnt)
 {
 	size_t winlen;
 	ssize_t sent = 0, ret;
-	int i;
+	int i, last;
+
+	/* the segment carrying the final byte gets PUSH */
+	for (last = iovcnt - 1; last > 0; last--) {
+		if (iov[last].iov_len > 0)
+			break;
+	}
 
 	/* block until the data can be sent */
-	ret = tcp_write_wait(c, &winlen);
+	ret = tcp_write_wait(c, false, &winlen);
 	if (ret)
 		return ret;
 
@@ -1014,8 +1228,11 @@ ssize_t tcp_writev(tcpconn_t *c, const struct iovec *iov, int iovcnt)
 	for (i = 0; i < iovcnt; i++, iov++) {
 		if (winlen <= 0)
 			break;
+		/* tcp_tx_send() would report an empty vector as a failure */
+		if (iov->iov_len == 0)
+			continue;
 		ret = tcp_tx_send(c, iov->iov_base, MIN(iov->iov_len, winlen),
-				  i == iovcnt - 1 && iov->iov_len <= winlen);
+				  i == last && iov->iov_len <= winlen);
 		if (ret <= 0)
 			break;
 		winlen -= ret;
@@ -1069,6 +1286,7 @@ void tcp_conn_fail(tcpconn_t *c, int err)
 	if (!c->tx_closed) {
 		c->tx_closed = true;
 		waitq_release(&c->tx_wq);
+		tcp_conn_poll_tx(c);
 	}
 
 	/* will be freed by the writer if one is busy */
@@ -1102,6 +1320,7 @@ void tcp_conn_shutdown_rx(tcpconn_t *c)
 
 	c->rx_closed = true;
 	waitq_release(&c->rx_wq);
+	tcp_conn_poll_rx(c);
 }
 
 static int tcp_conn_shutdown_tx(tcpconn_t *c)
@@ -1128,6 +1347,7 @@ static int tcp_conn_shutdown_tx(tcpconn_t *c)
 
 	c->tx_closed = true;
 	waitq_release(&c->tx_wq);
+	tcp_conn_poll_tx(c);
 
 	return 0;
 }
@@ -1206,6 +1426,8 @@ void tcp_close(tcpconn_t *c)
 
 	spin_lock_np(&c->lock);
 	BUG_ON(!waitq_empty(&c->rx_wq));
+	BUG_ON(c->rx_trig != NULL);
+	BUG_ON(c->tx_trig != NULL);
 	ret = tcp_conn_shutdown_tx(c);
 	if (ret)
 		tcp_conn_fail(c, -ret);
@@ -6,6 +6,7 @@
 #include <base/list.h>
 #include <base/kref.h>
 #include <base/time.h>
+#include <runtime/poll.h>
 #include <runtime/sync.h>
 #include <runtime/tcp.h>
 #include <net/tcp.h>
@@ -94,6 +95,7 @@ struct tcpconn {
 	unsigned int		rx_closed:1;
 	unsigned int		rx_exclusive:1;
 	waitq_t			rx_wq;
+	poll_trigger_t		*rx_trig;
 	unsigned int		rxq_ooo_len;
 	struct list_head	rxq_ooo;
 	struct list_head	rxq;
@@ -102,6 +104,7 @@ struct tcpconn {
 	unsigned int		tx_closed:1;
 	unsigned int		tx_exclusive:1;
 	waitq_t			tx_wq;
+	poll_trigger_t		*tx_trig;
 	uint32_t		tx_last_ack;
 	uint32_t		tx_last_win;
 	struct mbuf		*tx_pending;
@@ -110,6 +113,8 @@ struct tcpconn {
 	uint32_t		fast_retransmit_last_ack;
 
 	/* timeouts */
+	uint64_t		rx_timeout;
+	uint64_t		tx_timeout;
 	uint64_t 		next_timeout;
 	uint64_t		ack_ts;
 	union {
@@ -146,6 +151,50 @@ static inline tcpconn_t *tcp_conn_get(tcpconn_t *c)
 
 extern void tcp_conn_release_ref(struct kref *r);
 
+/**
+ * tcp_conn_poll_rx - fires the poll trigger if the connection is readable
+ * @c: the TCP connection
+ *
+ * The caller must hold @c's lock.
+ */
+static inline void tcp_conn_poll_rx(tcpconn_t *c)
+{
+	assert_spin_lock_held(&c->lock);
+
+	if (c->rx_trig && (c->rx_closed || !list_empty(&c->rxq)))
+		poll_trigger(c->rx_trig->waiter, c->rx_trig);
+}
+
+/**
+ * tcp_conn_writable - returns true if a write would not block
+ * @c: the TCP connection
+ *
+ * The caller must hold @c's lock.
+ */
+static inline bool tcp_conn_writable(tcpconn_t *c)
+{
+	assert_spin_lock_held(&c->lock);
+
+	/* an extra byte allows for window probing */
+	return c->tx_closed ||
+	       (c->pcb.state >= TCP_STATE_ESTABLISHED && !c->tx_exclusive &&
+		wraps_gt(c->pcb.snd_una + c->pcb.snd_wnd + 1, c->pcb.snd_nxt));
+}
+
+/**
+ * tcp_conn_poll_tx - fires the write poll trigger if the connection is writable
+ * @c: the TCP connection
+ *
+ * The caller must hold @c's lock.
+ */
+static inline void tcp_conn_poll_tx(tcpconn_t *c)
+{
+	assert_spin_lock_held(&c->lock);
+
+	if (c->tx_trig && tcp_conn_writable(c))
+		poll_trigger(c->tx_trig->waiter, c->tx_trig);
+}
+
 /**
  * tcp_conn_put - decrements the connection ref count
  * @c: the connection to decrement
@@ -261,6 +261,7 @@ void tcp_rx_conn(struct trans_entry *e, struct mbuf *m)
 	/* should we wake a thread */
 	if (!list_empty(&c->rxq) || (tcphdr->flags & TCP_PUSH) > 0)
 		rx_th = waitq_signal(&c->rx_wq, &c->lock);
+	tcp_conn_poll_rx(c);
 
 	/* handle delayed acks */
 	if (++c->acks_delayed_cnt >= 2) {
@@ -499,8 +500,10 @@ __tcp_rx_conn(tcpconn_t *c, struct mbuf *m, uint32_t ack, uint32_t snd_nxt,
 		if (c->pcb.snd_wnd != old_wnd)
 			c->rep_acks = 0;
 	}
-	if (snd_was_full && !is_snd_full(c))
+	if (snd_was_full && !is_snd_full(c)) {
 		waitq_release_start(&c->tx_wq, &waiters);
+		tcp_conn_poll_tx(c);
+	}
 
 	if (c->pcb.state == TCP_STATE_FIN_WAIT1 &&
 	    c->pcb.snd_una == snd_nxt) {
@@ -538,6 +541,7 @@ __tcp_rx_conn(tcpconn_t *c, struct mbuf *m, uint32_t ack, uint32_t snd_nxt,
 			assert(!list_empty(&c->rxq));
 			assert(do_drop == false);
 			rx_th = waitq_signal(&c->rx_wq, &c->lock);
+			tcp_conn_poll_rx(c);
 		}
 		if (++c->acks_delayed_cnt >= 2) {
 			do_ack = true;
@@ -45,23 +45,45 @@ struct udpconn {
 
 	/* ingress support */
 	spinlock_t		inq_lock;
+	uint64_t		inq_timeout;
 	int			inq_cap;
 	int			inq_len;
 	int			inq_err;
 	waitq_t			inq_wq;
+	poll_trigger_t		*inq_trig;
 	struct mbufq		inq;
 
 	/* egress support */
 	spinlock_t		outq_lock;
+	uint64_t		outq_timeout;
 	bool			outq_free;
 	int			outq_cap;
 	int			outq_len;
 	waitq_t			outq_wq;
+	poll_trigger_t		*outq_trig;
 
 	struct kref		ref;
 	struct flow_registration		flow;
 };
 
+/* fires the poll trigger if the socket is readable (inq_lock must be held) */
+static void udp_conn_poll_rx(udpconn_t *c)
+{
+	assert_spin_lock_held(&c->inq_lock);
+
+	if (c->inq_trig && (!mbufq_empty(&c->inq) || c->inq_err || c->shutdown))
+		poll_trigger(c->inq_trig->waiter, c->inq_trig);
+}
+
+/* fires the poll trigger if the socket is writable (outq_lock must be held) */
+static void udp_conn_poll_tx(udpconn_t *c)
+{
+	assert_spin_lock_held(&c->outq_lock);
+
+	if (c->outq_trig && (c->outq_len < c->outq_cap || c->shutdown))
+		poll_trigger(c->outq_trig->waiter, c->outq_trig);
+}
+
 /* handles ingress packets for UDP sockets */
 static void udp_conn_recv(struct trans_entry *e, struct mbuf *m)
 {
@@ -87,6 +109,7 @@ static void udp_conn_recv(struct trans_entry *e, struct mbuf *m)
 
 	/* wake up a waiter */
 	th = waitq_signal(&c->inq_wq, &c->inq_lock);
+	udp_conn_poll_rx(c);
 	spin_unlock_np(&c->inq_lock);
 
 	waitq_signal_finish(th);
@@ -102,6 +125,7 @@ static void udp_conn_err(struct trans_entry *e, int err)
 	spin_lock_np(&c->inq_lock);
 	do_release = !c->inq_err && !c->shutdown;
 	c->inq_err = err;
+	udp_conn_poll_rx(c);
 	spin_unlock_np(&c->inq_lock);
 
 	if (do_release)
@@ -120,18 +144,22 @@ static void udp_init_conn(udpconn_t *c)
 
 	/* initialize ingress fields */
 	spin_lock_init(&c->inq_lock);
+	c->inq_timeout = 0;
 	c->inq_cap = UDP_IN_DEFAULT_CAP;
 	c->inq_len = 0;
 	c->inq_err = 0;
 	waitq_init(&c->inq_wq);
+	c->inq_trig = NULL;
 	mbufq_init(&c->inq);
 
 	/* initialize egress fields */
 	spin_lock_init(&c->outq_lock);
+	c->outq_timeout = 0;
 	c->outq_free = false;
 	c->outq_cap = UDP_OUT_DEFAULT_CAP;
 	c->outq_len = 0;
 	waitq_init(&c->outq_wq);
+	c->outq_trig = NULL;
 
 	kref_init(&c->ref);
 }
@@ -277,46 +305,184 @@ int udp_set_buffers(udpconn_t *c, int read_mbufs, int write_mbufs)
 }
 
 /**
- * udp_read_from - reads from a UDP socket
+ * udp_poll_arm - fires a poll trigger whenever a UDP socket is readable
  * @c: the UDP socket
- * @buf: a buffer to store the datagram
- * @len: the size of @buf
- * @raddr: a pointer to store the remote address of the datagram (if not NULL)
+ * @w: the waiter to notify
+ * @t: the trigger to attach
+ * @data: data to provide to poll_wait() when the trigger fires
  *
- * WARNING: This a blocking function. It will wait until a datagram is
- * available, an error occurs, or the socket is shutdown.
+ * A socket is readable when it has a datagram queued, has an error pending,
+ * or has been shut down. The trigger fires immediately if that is already the
+ * case, and fires again after each read that leaves the socket readable. Only
+ * one trigger can be attached at a time.
  *
- * Returns the number of bytes in the datagram, or @len if the datagram
- * is >= @len in size. If the socket has been shutdown, returns 0.
+ * Returns 0 if successful, or -EBUSY if a trigger is already attached.
  */
-ssize_t udp_read_from(udpconn_t *c, void *buf, size_t len,
-                      struct netaddr *raddr)
+int udp_poll_arm(udpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+		 unsigned long data)
+{
+	spin_lock_np(&c->inq_lock);
+	if (c->inq_trig) {
+		spin_unlock_np(&c->inq_lock);
+		return -EBUSY;
+	}
+
+	poll_arm(w, t, data);
+	c->inq_trig = t;
+	udp_conn_poll_rx(c);
+	spin_unlock_np(&c->inq_lock);
+	return 0;
+}
+
+/**
+ * udp_poll_disarm - detaches a poll trigger from a UDP socket
+ * @c: the UDP socket
+ * @t: the trigger, previously attached with udp_poll_arm()
+ */
+void udp_poll_disarm(udpconn_t *c, poll_trigger_t *t)
+{
+	spin_lock_np(&c->inq_lock);
+	BUG_ON(c->inq_trig != t);
+	c->inq_trig = NULL;
+	spin_unlock_np(&c->inq_lock);
+
+	poll_disarm(t);
+}
+
+/**
+ * udp_poll_arm_tx - fires a poll trigger whenever a UDP socket is writable
+ * @c: the UDP socket
+ * @w: the waiter to notify
+ * @t: the trigger to attach
+ * @data: data to provide to poll_wait() when the trigger fires
+ *
+ * A socket is writable when its transmit buffer has space, or it has been
+ * shut down. The trigger fires immediately if that is already the case, and
+ * fires again each time a transmitted datagram frees space. Only one trigger
+ * can be attached at a time.
+ *
+ * Returns 0 if successful, or -EBUSY if a trigger is already attached.
+ */
+int udp_poll_arm_tx(udpconn_t *c, poll_waiter_t *w, poll_trigger_t *t,
+		    unsigned long data)
+{
+	spin_lock_np(&c->outq_lock);
+	if (c->outq_trig) {
+		spin_unlock_np(&c->outq_lock);
+		return -EBUSY;
+	}
+
+	poll_arm(w, t, data);
+	c->outq_trig = t;
+	udp_conn_poll_tx(c);
+	spin_unlock_np(&c->outq_lock);
+	return 0;
+}
+
+/**
+ * udp_poll_disarm_tx - detaches a write poll trigger from a UDP socket
+ * @c: the UDP socket
+ * @t: the trigger, previously attached with udp_poll_arm_tx()
+ */
+void udp_poll_disarm_tx(udpconn_t *c, poll_trigger_t *t)
 {
+	spin_lock_np(&c->outq_lock);
+	BUG_ON(c->outq_trig != t);
+	c->outq_trig = NULL;
+	spin_unlock_np(&c->outq_lock);
+
+	poll_disarm(t);
+}
+
+/**
+ * udp_set_rx_timeout - bounds how long a read may block
+ * @c: the UDP socket
+ * @timeout_us: the timeout in microseconds, or 0 to block indefinitely
+ *
+ * A read that times out returns -ETIMEDOUT and leaves the socket intact.
+ */
+void udp_set_rx_timeout(udpconn_t *c, uint64_t timeout_us)
+{
+	spin_lock_np(&c->inq_lock);
+	c->inq_timeout = timeout_us;
+	spin_unlock_np(&c->inq_lock);
+}
+
+/**
+ * udp_set_tx_timeout - bounds how long a write may block
+ * @c: the UDP socket
+ * @timeout_us: the timeout in microseconds, or 0 to block indefinitely
+ *
+ * A write that times out returns -ETIMEDOUT and leaves the socket intact.
+ */
+void udp_set_tx_timeout(udpconn_t *c, uint64_t timeout_us)
+{
+	spin_lock_np(&c->outq_lock);
+	c->outq_timeout = timeout_us;
+	spin_unlock_np(&c->outq_lock);
+}
+
+/**
+ * udp_get_rx_timeout - gets the read timeout in microseconds (0 if none)
+ * @c: the UDP socket
+ */
+uint64_t udp_get_rx_timeout(udpconn_t *c)
+{
+	return ACCESS_ONCE(c->inq_timeout);
+}
+
+/**
+ * udp_get_tx_timeout - gets the write timeout in microseconds (0 if none)
+ * @c: the UDP socket
+ */
+uint64_t udp_get_tx_timeout(udpconn_t *c)
+{
+	return ACCESS_ONCE(c->outq_timeout);
+}
+
+static ssize_t __udp_read_from(udpconn_t *c, void *buf, size_t len,
+			       struct netaddr *raddr, bool nonblock)
+{
+	struct waitq_timeout t;
 	ssize_t ret;
 	struct mbuf *m;
 
 	spin_lock_np(&c->inq_lock);
+	waitq_timeout_init(&t, &c->inq_wq, &c->inq_lock, c->inq_timeout);
 
 	/* block until there is an actionable event */
-	while (mbufq_empty(&c->inq) && !c->inq_err && !c->shutdown)
-		waitq_wait(&c->inq_wq, &c->inq_lock);
+	while (mbufq_empty(&c->inq) && !c->inq_err && !c->shutdown) {
+		if (nonblock) {
+			spin_unlock_np(&c->inq_lock);
+			return -EAGAIN;
+		}
+		if (!waitq_wait_timeout(&t)) {
+			spin_unlock_np(&c->inq_lock);
+			waitq_timeout_finish(&t);
+			return -ETIMEDOUT;
+		}
+	}
 
 	/* is the socket drained and shutdown? */
 	if (mbufq_empty(&c->inq) && c->shutdown) {
 		spin_unlock_np(&c->inq_lock);
+		waitq_timeout_finish(&t);
 		return 0;
 	}
 
 	/* propagate error status code if an error was detected */
 	if (c->inq_err) {
 		spin_unlock_np(&c->inq_lock);
+		waitq_timeout_finish(&t);
 		return -c->inq_err;
 	}
 
 	/* pop an mbuf and deliver the payload */
 	m = mbufq_pop_head(&c->inq);
 	c->inq_len--;
+	udp_conn_poll_rx(c);
 	spin_unlock_np(&c->inq_lock);
+	waitq_timeout_finish(&t);
 
 	ret = MIN(len, mbuf_length(m));
 	memcpy(buf, mbuf_data(m), ret);
@@ -334,6 +500,40 @@ ssize_t udp_read_from(udpconn_t *c, void *buf, size_t len,
 	return ret;
 }
 
+/**
+ * udp_read_from - reads from a UDP socket
+ * @c: the UDP socket
+ * @buf: a buffer to store the datagram
+ * @len: the size of @buf
+ * @raddr: a pointer to store the remote address of the datagram (if not NULL)
+ *
+ * WARNING: This a blocking function. It will wait until a datagram is
+ * available, an error occurs, or the socket is shutdown.
+ *
+ * Returns the number of bytes in the datagram, or @len if the datagram
+ * is >= @len in size. If the socket has been shutdown, returns 0.
+ */
+ssize_t udp_read_from(udpconn_t *c, void *buf, size_t len,
+                      struct netaddr *raddr)
+{
+	return __udp_read_from(c, buf, len, raddr, false);
+}
+
+/**
+ * udp_read_from_nonblock - reads from a UDP socket without blocking
+ * @c: the UDP socket
+ * @buf: a buffer to store the datagram
+ * @len: the size of @buf
+ * @raddr: a pointer to store the remote address of the datagram (if not NULL)
+ *
+ * Like udp_read_from(), but returns -EAGAIN instead of waiting for a datagram.
+ */
+ssize_t udp_read_from_nonblock(udpconn_t *c, void *buf, size_t len,
+			       struct netaddr *raddr)
+{
+	return __udp_read_from(c, buf, len, raddr, true);
+}
+
 static void udp_tx_release_mbuf(struct mbuf *m)
 {
 	udpconn_t *c = (udpconn_t *)m->release_data;
@@ -345,6 +545,7 @@ static void udp_tx_release_mbuf(struct mbuf *m)
 	free_conn = (c->outq_free && c->outq_len == 0);
 	if (!c->shutdown)
 		th = waitq_signal(&c->outq_wq, &c->outq_lock);
+	udp_conn_poll_tx(c);
 	spin_unlock_np(&c->outq_lock);
 	waitq_signal_finish(th);
 
@@ -353,22 +554,10 @@ static void udp_tx_release_mbuf(struct mbuf *m)
 		udp_conn_put(c);
 }
 
-/**
- * udp_write_to - writes to a UDP socket
- * @c: the UDP socket
- * @buf: a buffer from which to load the payload
- * @len: the length of the payload
- * @raddr: the remote address of the datagram (if not NULL)
- *
- * WARNING: This a blocking function. It will wait until space in the transmit
- * buffer is available or the socket is shutdown.
- *
- * Returns the number of payload bytes sent in the datagram. If an error
- * occurs, returns < 0 to indicate the error code.
- */
-ssize_t udp_write_to(udpconn_t *c, const void *buf, size_t len,
-                     const struct netaddr *raddr)
+static ssize_t __udp_write_to(udpconn_t *c, const void *buf, size_t len,
+			      const struct netaddr *raddr, bool nonblock)
 {
+	struct waitq_timeout t;
 	struct netaddr addr;
 	ssize_t ret;
 	struct mbuf *m;
@@ -385,19 +574,31 @@ ssize_t udp_write_to(udpconn_t *c, const void *buf, size_t len,
 	}
 
 	spin_lock_np(&c->outq_lock);
+	waitq_timeout_init(&t, &c->outq_wq, &c->outq_lock, c->outq_timeout);
 
 	/* block until there is an actionable event */
-	while (c->outq_len >= c->outq_cap && !c->shutdown)
-		waitq_wait(&c->outq_wq, &c->outq_lock);
+	while (c->outq_len >= c->outq_cap && !c->shutdown) {
+		if (nonblock) {
+			spin_unlock_np(&c->outq_lock);
+			return -EAGAIN;
+		}
+		if (!waitq_wait_timeout(&t)) {
+			spin_unlock_np(&c->outq_lock);
+			waitq_timeout_finish(&t);
+			return -ETIMEDOUT;
+		}
+	}
 
 	/* is the socket shutdown? */
 	if (c->shutdown) {
 		spin_unlock_np(&c->outq_lock);
+		waitq_timeout_finish(&t);
 		return -EPIPE;
 	}
 
 	c->outq_len++;
 	spin_unlock_np(&c->outq_lock);
+	waitq_timeout_finish(&t);
 
 	m = net_tx_alloc_mbuf();
 	if (unlikely(!m))
@@ -420,6 +621,41 @@ ssize_t udp_write_to(udpconn_t *c, const void *buf, size_t len,
 	return len;
 }
 
+/**
+ * udp_write_to - writes to a UDP socket
+ * @c: the UDP socket
+ * @buf: a buffer from which to load the payload
+ * @len: the length of the payload
+ * @raddr: the remote address of the datagram (if not NULL)
+ *
+ * WARNING: This a blocking function. It will wait until space in the transmit
+ * buffer is available or the socket is shutdown.
+ *
+ * Returns the number of payload bytes sent in the datagram. If an error
+ * occurs, returns < 0 to indicate the error code.
+ */
+ssize_t udp_write_to(udpconn_t *c, const void *buf, size_t len,
+                     const struct netaddr *raddr)
+{
+	return __udp_write_to(c, buf, len, raddr, false);
+}
+
+/**
+ * udp_write_to_nonblock - writes to a UDP socket without blocking
+ * @c: the UDP socket
+ * @buf: a buffer from which to load the payload
+ * @len: the length of the payload
+ * @raddr: the remote address of the datagram (if not NULL)
+ *
+ * Like udp_write_to(), but returns -EAGAIN instead of waiting for space in
+ * the transmit buffer.
+ */
+ssize_t udp_write_to_nonblock(udpconn_t *c, const void *buf, size_t len,
+			      const struct netaddr *raddr)
+{
+	return __udp_write_to(c, buf, len, raddr, true);
+}
+
 /**
  * udp_read - reads from a UDP socket
  * @c: the UDP socket
@@ -460,7 +696,9 @@ static void __udp_shutdown(udpconn_t *c)
 	spin_lock_np(&c->outq_lock);
 	BUG_ON(c->shutdown);
 	c->shutdown = true;
+	udp_conn_poll_tx(c);
 	spin_unlock_np(&c->outq_lock);
+	udp_conn_poll_rx(c);
 	spin_unlock_np(&c->inq_lock);
 
 	/* prevent ingress receive and error dispatch (after RCU period) */
@@ -500,6 +738,8 @@ void udp_close(udpconn_t *c)
 
 	BUG_ON(!waitq_empty(&c->inq_wq));
 	BUG_ON(!waitq_empty(&c->outq_wq));
+	BUG_ON(c->inq_trig != NULL);
+	BUG_ON(c->outq_trig != NULL);
 
 	/* free all in-flight mbufs */
 	while (true) {
@@ -529,17 +769,38 @@ void udp_close(udpconn_t *c)
 struct udpspawner {
 	struct trans_entry	e;
 	udpspawn_fn_t		fn;
+	void			*arg;
+	udpspawn_release_fn_t	release;
 
 	struct kref ref;
 	struct flow_registration flow;
 };
 
+/* the stack buffer of a spawned handler thread */
+struct udp_spawn_ctx {
+	struct udp_spawn_data	d;
+	udpspawner_t		*s;
+};
+
+static void udp_release_spawner_ref(struct kref *ref);
+
+/* runs a handler, keeping the spawner alive until it returns */
+static void udp_par_run(void *arg)
+{
+	struct udp_spawn_ctx *ctx = arg;
+	udpspawner_t *s = ctx->s;
+
+	s->fn(&ctx->d);
+	kref_put(&s->ref, udp_release_spawner_ref);
+}
+
 /* handles ingress packets with parallel threads */
 static void udp_par_recv(struct trans_entry *e, struct mbuf *m)
 {
 	udpspawner_t *s = container_of(e, udpspawner_t, e);
 	const struct ip_hdr *iphdr;
 	const struct udp_hdr *udphdr;
+	struct udp_spawn_ctx *ctx;
 	struct udp_spawn_data *d;
 	thread_t *th;
 
@@ -550,19 +811,28 @@ static void udp_par_recv(struct trans_entry *e, struct mbuf *m)
 		return;
 	}
 
-	th = thread_create_with_buf((thread_fn_t)s->fn,
-				    (void **)&d, sizeof(*d));
+	/* the spawner may be concurrently destroyed */
+	if (unlikely(!kref_get_unless_zero(&s->ref))) {
+		mbuf_drop(m);
+		return;
+	}
+
+	th = thread_create_with_buf(udp_par_run, (void **)&ctx, sizeof(*ctx));
 	if (unlikely(!th)) {
+		kref_put(&s->ref, udp_release_spawner_ref);
 		mbuf_drop(m);
 		return;
 	}
 
+	ctx->s = s;
+	d = &ctx->d;
 	d->buf = mbuf_data(m);
 	d->len = mbuf_length(m);
 	d->laddr = e->laddr;
 	d->raddr.ip = ntoh32(iphdr->saddr);
 	d->raddr.port = ntoh16(udphdr->src_port);
 	d->release_data = m;
+	d->arg = s->arg;
 	thread_ready(th);
 }
 
@@ -574,6 +844,9 @@ static const struct trans_ops udp_par_ops = {
 static void udp_release_spawner(struct rcu_head *h)
 {
 	udpspawner_t *s = container_of(h, udpspawner_t, e.rcu);
+
+	if (s->release)
+		s->release(s->arg);
 	sfree(s);
 }
 
@@ -585,15 +858,19 @@ static void udp_release_spawner_ref(struct kref *ref)
 
 
 /**
- * udp_create_spawner - creates a UDP spawner for ingress datagrams
+ * udp_create_spawner_arg - creates a UDP spawner with handler state
  * @laddr: the local address to bind to
  * @fn: a handler function for each datagram
+ * @arg: passed to @fn as the arg field of struct udp_spawn_data
+ * @release: if not NULL, called with @arg once the spawner has been destroyed
+ *	     and every handler has returned
  * @s_out: if successful, set to a pointer to the spawner
  *
  * Returns 0 if successful, otherwise fail.
  */
-int udp_create_spawner(struct netaddr laddr, udpspawn_fn_t fn,
-		       udpspawner_t **s_out)
+int udp_create_spawner_arg(struct netaddr laddr, udpspawn_fn_t fn,
+			   void *arg, udpspawn_release_fn_t release,
+			   udpspawner_t **s_out)
 {
 	udpspawner_t *s;
 	int ret;
@@ -611,6 +888,8 @@ int udp_create_spawner(struct netaddr laddr, udpspawn_fn_t fn,
 	kref_init(&s->ref);
 	trans_init_3tuple(&s->e, IPPROTO_UDP, &udp_par_ops, laddr);
 	s->fn = fn;
+	s->arg = arg;
+	s->release = release;
 	ret = trans_table_add(&s->e);
 	if (ret) {
 		sfree(s);
@@ -627,6 +906,20 @@ int udp_create_spawner(struct netaddr laddr, udpspawn_fn_t fn,
 	return 0;
 }
 
+/**
+ * udp_create_spawner - creates a UDP spawner for ingress datagrams
+ * @laddr: the local address to bind to
+ * @fn: a handler function for each datagram
+ * @s_out: if successful, set to a pointer to the spawner
+ *
+ * Returns 0 if successful, otherwise fail.
+ */
+int udp_create_spawner(struct netaddr laddr, udpspawn_fn_t fn,
+		       udpspawner_t **s_out)
+{
+	return udp_create_spawner_arg(laddr, fn, NULL, NULL, s_out);
+}
+
 /**
  * udp_destroy_spawner - unregisters and frees a UDP spawner
  * @s: the spawner to free
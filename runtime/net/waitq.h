@@ -6,8 +6,10 @@
 #pragma once
 
 #include <base/list.h>
+#include <base/time.h>
 #include <runtime/thread.h>
 #include <runtime/sync.h>
+#include <runtime/timer.h>
 
 typedef struct waitq {
 	struct list_head	waiters;
@@ -112,3 +114,92 @@ static inline void waitq_init(waitq_t *q)
 {
 	list_head_init(&q->waiters);
 }
+
+
+/*
+ * Timed waits
+ */
+
+struct waitq_timeout {
+	struct timer_entry	e;
+	waitq_t			*q;
+	spinlock_t		*l;
+	uint64_t		deadline_us;
+	bool			armed;
+	bool			finished;
+};
+
+static inline void waitq_timeout_fire(unsigned long arg)
+{
+	struct waitq_timeout *t = (struct waitq_timeout *)arg;
+	struct list_head waiters;
+
+	list_head_init(&waiters);
+	spin_lock_np(t->l);
+	waitq_release_start(t->q, &waiters);
+	spin_unlock_np(t->l);
+	waitq_release_finish(&waiters);
+
+	/* @t may go out of scope as soon as this is visible */
+	store_release(&t->finished, true);
+}
+
+/**
+ * waitq_timeout_init - prepares a timeout for waiting on a wake queue
+ * @t: the timeout state (typically on the waiter's stack)
+ * @q: the wake queue
+ * @l: the spinlock protecting the wake queue
+ * @timeout_us: how long to wait in microseconds, or 0 to wait forever
+ */
+static inline void waitq_timeout_init(struct waitq_timeout *t, waitq_t *q,
+				      spinlock_t *l, uint64_t timeout_us)
+{
+	timer_init(&t->e, waitq_timeout_fire, (unsigned long)t);
+	t->q = q;
+	t->l = l;
+	t->deadline_us = timeout_us ? microtime() + timeout_us : 0;
+	t->armed = false;
+	t->finished = false;
+}
+
+/**
+ * waitq_wait_timeout - waits for the next signal or for a deadline to pass
+ * @t: the timeout state, initialized with waitq_timeout_init()
+ *
+ * The lock passed to waitq_timeout_init() must be held. When the deadline
+ * passes, every waiter on the wake queue is released, so wakeups can be
+ * spurious and the caller must recheck its condition.
+ *
+ * Returns false without waiting if the deadline has already passed.
+ */
+static inline bool waitq_wait_timeout(struct waitq_timeout *t)
+{
+	if (t->deadline_us) {
+		if (microtime() >= t->deadline_us)
+			return false;
+		if (!t->armed) {
+			t->armed = true;
+			timer_start(&t->e, t->deadline_us);
+		}
+	}
+
+	waitq_wait(t->q, t->l);
+	return true;
+}
+
+/**
+ * waitq_timeout_finish - releases timeout state after a timed wait
+ * @t: the timeout state
+ *
+ * Must be called without the wake queue lock held, before @t goes out of
+ * scope.
+ */
+static inline void waitq_timeout_finish(struct waitq_timeout *t)
+{
+	if (!t->armed || timer_cancel(&t->e))
+		return;
+
+	/* the timer already fired, wait for its handler to stop using @t */
+	while (!load_acquire(&t->finished))
+		cpu_relax();
+}
@@ -172,7 +172,7 @@ static bool work_available(struct kthread *k)
 #endif
 
 	return ACCESS_ONCE(k->rq_tail) != ACCESS_ONCE(k->rq_head) ||
-	       softirq_pending(k);
+	       !list_empty(&k->rq_overflow) || softirq_pending(k);
 }
 
 static void update_oldest_tsc(struct kthread *k)
@@ -342,6 +342,13 @@ static __noreturn __noinline void schedule(void)
 	l->rq_head = l->rq_tail = 0;
 
 again:
+	/* pick up threads other kthreads queued here while we were idle */
+	if (unlikely(!list_empty(&l->rq_overflow))) {
+		drain_overflow(l);
+		if (l->rq_head != l->rq_tail)
+			goto done;
+	}
+
 	/* then check for local softirqs */
 	if (softirq_sched(l)) {
 		STAT(SOFTIRQS_LOCAL)++;
@@ -653,6 +660,50 @@ void thread_ready(thread_t *th)
 	putk();
 }
 
+/**
+ * thread_ready_on - makes a uthread runnable on a specific kthread
+ * @th: the thread to mark runnable
+ * @kidx: the index of the kthread to queue @th on
+ *
+ * @th is queued behind the work already on that kthread, and may still be
+ * stolen by an idle kthread. This function can only be called when @th is
+ * parked.
+ *
+ * Returns 0 if successful, or -EINVAL if @kidx is not a valid kthread.
+ */
+int thread_ready_on(thread_t *th, unsigned int kidx)
+{
+	struct kthread *k, *r;
+
+	if (kidx >= nrks)
+		return -EINVAL;
+
+	k = getk();
+	r = ks[kidx];
+	if (r == k) {
+		thread_ready(th);
+		putk();
+		return 0;
+	}
+
+	/*
+	 * Only the owning kthread may push to its runqueue, so go through the
+	 * overflow list instead. Likewise only the owner advances rq_head, so
+	 * account for @th in rq_remote, which the IOKernel adds to rq_head;
+	 * rq_remote is only ever updated with the kthread lock held.
+	 */
+	thread_ready_prepare(r, th);
+	spin_lock(&r->lock);
+	list_add_tail(&r->rq_overflow, &th->link);
+	if (ACCESS_ONCE(r->q_ptrs->rq_head) + r->q_ptrs->rq_remote ==
+	    ACCESS_ONCE(r->q_ptrs->rq_tail))
+		ACCESS_ONCE(r->q_ptrs->oldest_tsc) = th->ready_tsc;
+	ACCESS_ONCE(r->q_ptrs->rq_remote)++;
+	spin_unlock(&r->lock);
+	putk();
+	return 0;
+}
+
 /**
  * thread_ready_head - makes a uthread runnable (at the head of the queue)
  * @th: the thread to mark runnable
@@ -770,6 +821,7 @@ static __always_inline thread_t *__thread_create(void)
 	th->thread_ready = false;
 	th->thread_running = false;
 	th->run_start_tsc = UINT64_MAX;
+	th->tlsvar = 0;
 
 	return th;
 }
@@ -20,6 +20,7 @@ BUILD_ASSERT(SMALLOC_MIN_SIZE >= SLAB_MIN_SIZE);
 static struct slab smalloc_slabs[SMALLOC_BITS];
 static struct tcache *smalloc_tcaches[SMALLOC_BITS];
 static DEFINE_PERTHREAD(struct tcache_perthread, smalloc_pts[SMALLOC_BITS]);
+__thread bool smalloc_thread_ready;
 
 /**
  * smalloc_size_to_idx - converts a size to a cache index
@@ -104,6 +105,18 @@ void sfree(void *item)
 	preempt_enable();
 }
 
+/**
+ * smalloc_owns - determines if an item was allocated by smalloc
+ * @item: the item
+ *
+ * Useful for allocators that fall back to libc malloc() for items smalloc
+ * cannot satisfy.
+ */
+bool smalloc_owns(void *item)
+{
+	return is_page_addr(item);
+}
+
 /**
  * smalloc_init - initializes slab malloc
  *
@@ -140,5 +153,6 @@ int smalloc_init_thread(void)
 		tcache_init_perthread(smalloc_tcaches[i],
 				      &perthread_get(smalloc_pts[i]));
 
+	smalloc_thread_ready = true;
 	return 0;
 }
@@ -7,12 +7,14 @@
 
 uint32_t block_size;
 uint64_t num_blocks;
+uint32_t max_xfer_blocks;
 
 #ifdef DIRECT_STORAGE
 #include <stdio.h>
 #include <base/hash.h>
 #include <base/log.h>
 #include <base/mempool.h>
+#include <runtime/smalloc.h>
 #include <runtime/sync.h>
 
 // Hack to prevent SPDK from pulling in extra headers here
@@ -88,6 +90,8 @@ static void attach_cb(void *cb_ctx, const struct spdk_nvme_transport_id *trid,
 	spdk_namespace = spdk_nvme_ctrlr_get_ns(ctrlr, 1);
 	block_size = spdk_nvme_ns_get_sector_size(spdk_namespace);
 	num_blocks = spdk_nvme_ns_get_num_sectors(spdk_namespace);
+	max_xfer_blocks =
+		spdk_nvme_ns_get_max_io_xfer_size(spdk_namespace) / block_size;
 
 	for (i = 0; i < ARRAY_SIZE(known_devices); i++) {
 		if (!strncmp((char *)ctrlr_data->mn, known_devices[i].name,
@@ -117,7 +121,7 @@ int storage_write(const void *payload, uint64_t lba, uint32_t lba_count)
 	if (!cfg_storage_enabled)
 		return -ENODEV;
 
-	size_t req_size = lba_count * block_size;
+	size_t req_size = (size_t)lba_count * block_size;
 	bool use_thread_cache = req_size <= REQUEST_BUF_SZ;
 
 	k = getk();
@@ -181,7 +185,7 @@ int storage_read(void *dest, uint64_t lba, uint32_t lba_count)
 	if (!cfg_storage_enabled)
 		return -ENODEV;
 
-	size_t req_size = lba_count * block_size;
+	size_t req_size = (size_t)lba_count * block_size;
 	bool use_thread_cache = req_size <= REQUEST_BUF_SZ;
 
 	k = getk();
@@ -226,6 +230,148 @@ done_np:
 	return rc;
 }
 
+/* an in-flight request submitted with storage_read/write_async() */
+struct storage_async_req {
+	void			*payload;
+	void			*dest;
+	size_t			req_size;
+	bool			use_thread_cache;
+	storage_cb_fn_t		cb;
+	void			*arg;
+};
+
+static void storage_async_free_payload(struct storage_async_req *req)
+{
+	if (likely(req->use_thread_cache))
+		tcache_free(&perthread_get(storage_buf_pt), req->payload);
+	else
+		spdk_free(req->payload);
+}
+
+static void async_complete(void *arg, const struct spdk_nvme_cpl *completion)
+{
+	struct storage_async_req *req = arg;
+	int status = spdk_nvme_cpl_is_error(completion) ? -EIO : 0;
+
+	if (req->dest && !status)
+		memcpy(req->dest, req->payload, req->req_size);
+	storage_async_free_payload(req);
+	req->cb(req->arg, status);
+	sfree(req);
+}
+
+static int storage_submit_async(void *dest, const void *payload, uint64_t lba,
+				uint32_t lba_count, storage_cb_fn_t cb,
+				void *arg)
+{
+	int rc;
+	struct kthread *k;
+	struct storage_q *q;
+	struct storage_async_req *req;
+
+	if (!cfg_storage_enabled)
+		return -ENODEV;
+	if (unlikely(lba_count > max_xfer_blocks))
+		return -EINVAL;
+
+	req = smalloc(sizeof(*req));
+	if (unlikely(!req))
+		return -ENOMEM;
+
+	req->req_size = (size_t)lba_count * block_size;
+	req->use_thread_cache = req->req_size <= REQUEST_BUF_SZ;
+	req->dest = dest;
+	req->cb = cb;
+	req->arg = arg;
+
+	k = getk();
+	q = &k->storage_q;
+
+	if (likely(req->use_thread_cache)) {
+		req->payload = tcache_alloc(&perthread_get(storage_buf_pt));
+	} else {
+		req->payload =
+			spdk_zmalloc(req->req_size, 0, NULL,
+				     SPDK_ENV_SOCKET_ID_ANY, SPDK_MALLOC_DMA);
+	}
+
+	if (unlikely(req->payload == NULL)) {
+		rc = -ENOMEM;
+		goto fail_req;
+	}
+
+	if (payload)
+		memcpy(req->payload, payload, req->req_size);
+
+	spin_lock(&q->lock);
+	if (payload) {
+		rc = spdk_nvme_ns_cmd_write(spdk_namespace, q->spdk_qp_handle,
+					    req->payload, lba, lba_count,
+					    async_complete, req, 0);
+	} else {
+		rc = spdk_nvme_ns_cmd_read(spdk_namespace, q->spdk_qp_handle,
+					   req->payload, lba, lba_count,
+					   async_complete, req, 0);
+	}
+
+	if (unlikely(rc != 0)) {
+		spin_unlock(&q->lock);
+		storage_async_free_payload(req);
+		rc = -EIO;
+		goto fail_req;
+	}
+
+	q->outstanding_reqs++;
+	spin_unlock(&q->lock);
+	putk();
+	return 0;
+
+fail_req:
+	putk();
+	sfree(req);
+	return rc;
+}
+
+/**
+ * storage_write_async - submit a write to the nvme device without blocking
+ * @payload: lba_count*storage_block_size() bytes to write, copied before
+ *           returning
+ * @lba: the first block to write
+ * @lba_count: the number of blocks to write, at most
+ *             storage_max_xfer_blocks()
+ * @cb: called with 0 or -EIO once the write completes
+ * @arg: an argument passed to @cb
+ *
+ * @cb runs in the runtime's softirq context and must not block. It is called
+ * exactly once if this function returns 0, and never otherwise.
+ *
+ * Returns 0 if submitted, -EINVAL if @lba_count is too large, -ENOMEM if no
+ * available memory, and -EIO if the device refused the request.
+ */
+int storage_write_async(const void *payload, uint64_t lba, uint32_t lba_count,
+			storage_cb_fn_t cb, void *arg)
+{
+	return storage_submit_async(NULL, payload, lba, lba_count, cb, arg);
+}
+
+/**
+ * storage_read_async - submit a read from the nvme device without blocking
+ * @dest: lba_count*storage_block_size() bytes, filled in before @cb runs; must
+ *        stay valid until then
+ * @lba: the first block to read
+ * @lba_count: the number of blocks to read, at most
+ *             storage_max_xfer_blocks()
+ * @cb: called with 0 or -EIO once the read completes
+ * @arg: an argument passed to @cb
+ *
+ * Same completion rules and return values as storage_write_async().
+ */
+int storage_read_async(void *dest, uint64_t lba, uint32_t lba_count,
+		       storage_cb_fn_t cb, void *arg)
+{
+	return storage_submit_async(dest, NULL, lba, lba_count, cb, arg);
+}
+
 static int storage_softirq_one(struct storage_q *q)
 {
 	int ret;
@@ -419,6 +565,18 @@ int storage_read(void *dest, uint64_t lba, uint32_t lba_count)
 	return -ENODEV;
 }
 
+int storage_write_async(const void *payload, uint64_t lba, uint32_t lba_count,
+			storage_cb_fn_t cb, void *arg)
+{
+	return -ENODEV;
+}
+
+int storage_read_async(void *dest, uint64_t lba, uint32_t lba_count,
+		       storage_cb_fn_t cb, void *arg)
+{
+	return -ENODEV;
+}
+
 int storage_init(void)
 {
 	return 0;
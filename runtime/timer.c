@@ -269,11 +269,15 @@ static void timer_softirq_one(struct kthread *k)
 			k->timers[0].e->idx = 0;
 			sift_down(k->timers, 0, i);
 		}
+		/*
+		 * Disarm before dropping the lock so that a concurrent
+		 * timer_cancel() fails instead of touching a stale heap index.
+		 */
+		e->armed = false;
 		update_q_ptrs(k);
 		spin_unlock(&k->timer_lock);
 
 		/* execute the timer handler */
-		e->armed = false;
 		e->fn(e->arg);
 		spin_lock(&k->timer_lock);
 		now_us = microtime();